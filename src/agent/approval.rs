@@ -0,0 +1,123 @@
+use std::{future::Future, pin::Pin};
+
+use rig::{
+    completion::ToolDefinition,
+    tool::{ToolDyn, ToolError},
+};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::mcp::policy::ApprovalPolicy;
+
+tokio::task_local! {
+    /// Where the tools of the current request ask for approval
+    static APPROVER: mpsc::UnboundedSender<ApprovalRequest>;
+}
+
+/// A tool call waiting for the user
+pub struct ApprovalRequest {
+    pub name: String,
+    pub arguments: serde_json::Value,
+    reply: oneshot::Sender<bool>,
+}
+
+impl ApprovalRequest {
+    pub fn answer(self, approved: bool) {
+        let _ = self.reply.send(approved);
+    }
+}
+
+/// Run `future`, the [`ApprovedTool`]s it calls send their requests to
+/// `approver`, whose receiver must be answered while `future` is polled
+pub async fn approving<F: Future>(
+    approver: mpsc::UnboundedSender<ApprovalRequest>,
+    future: F,
+) -> F::Output {
+    APPROVER.scope(approver, future).await
+}
+
+/// Refused tool call, reported to the model
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct Refused(String);
+
+/// Tool following its [`ApprovalPolicy`], asking the user before every call
+/// if it is [`ApprovalPolicy::Ask`]
+pub struct ApprovedTool<T> {
+    tool: T,
+    policy: ApprovalPolicy,
+}
+
+impl<T> ApprovedTool<T> {
+    pub fn new(tool: T, policy: ApprovalPolicy) -> Self {
+        Self { tool, policy }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.tool
+    }
+}
+
+impl<T: ToolDyn> ApprovedTool<T> {
+    async fn approve(&self, args: &str) -> Result<(), Refused> {
+        match self.policy {
+            ApprovalPolicy::Allow => return Ok(()),
+            ApprovalPolicy::Deny => {
+                return Err(Refused(format!("`{}` is denied", self.tool.name())));
+            }
+            ApprovalPolicy::Ask => {}
+        }
+
+        let arguments = serde_json::from_str(args)
+            .unwrap_or_else(|_| serde_json::Value::String(args.to_string()));
+        let (reply, answer) = oneshot::channel();
+        let request = ApprovalRequest {
+            name: self.tool.name(),
+            arguments,
+            reply,
+        };
+
+        let sent = APPROVER
+            .try_with(|approver| approver.send(request).is_ok())
+            .unwrap_or_default();
+        if !sent {
+            return Err(Refused(format!(
+                "`{}` needs the approval of the user, who can not be asked here",
+                self.tool.name()
+            )));
+        }
+
+        match answer.await {
+            Ok(true) => Ok(()),
+            _ => Err(Refused(format!(
+                "The user refused the call of `{}`",
+                self.tool.name()
+            ))),
+        }
+    }
+}
+
+impl<T: ToolDyn> ToolDyn for ApprovedTool<T> {
+    fn name(&self) -> String {
+        self.tool.name()
+    }
+
+    fn definition(
+        &self,
+        prompt: String,
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + Sync + '_>> {
+        self.tool.definition(prompt)
+    }
+
+    fn call(
+        &self,
+        args: String,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + '_>> {
+        Box::pin(async move {
+            self.approve(&args)
+                .await
+                .map_err(|e| ToolError::ToolCallError(Box::new(e)))?;
+
+            self.tool.call(args).await
+        })
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rig::{
+    OneOrMany,
+    completion::Message,
+    message::{ImageMediaType, UserContent},
+};
+use serde::{Deserialize, Serialize};
+
+/// Characters allowed to follow a path mentioned in a sentence
+const TRAILING_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?', ')', ']', '"', '\''];
+
+/// Limits of the files attached with `@path`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttachmentConfig {
+    /// Bytes of a text file inlined in the prompt, the rest is cut
+    #[serde(default = "default_max_text_bytes")]
+    pub max_text_bytes: usize,
+    /// Bytes of an image, larger ones are refused
+    #[serde(default = "default_max_image_bytes")]
+    pub max_image_bytes: usize,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            max_text_bytes: default_max_text_bytes(),
+            max_image_bytes: default_max_image_bytes(),
+        }
+    }
+}
+
+fn default_max_text_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_image_bytes() -> usize {
+    5 * 1024 * 1024
+}
+
+/// Message of a prompt typed by the user, with the files it mentions as
+/// `@path` attached.
+///
+/// Text files are inlined after the prompt, images are sent as image content,
+/// which providers without image input leave out. Mentions which are not
+/// files, e.g. `@someone`, are left as they are.
+pub fn prompt_message(input: &str, config: &AttachmentConfig) -> anyhow::Result<Message> {
+    let mut content = OneOrMany::one(UserContent::text(input));
+    let mut attached: Vec<&str> = Vec::new();
+
+    for word in input.split_whitespace() {
+        let Some(path) = word.strip_prefix('@').and_then(mentioned_file) else {
+            continue;
+        };
+        if attached.contains(&path) {
+            continue;
+        }
+        attached.push(path);
+
+        content.push(attach(Path::new(path), config)?);
+    }
+
+    Ok(Message::User { content })
+}
+
+/// Text typed by the user, without the attached files
+pub fn prompt_text(message: &Message) -> String {
+    match message {
+        Message::User { content } => match content.first() {
+            UserContent::Text(text) => text.text,
+            _ => String::new(),
+        },
+        Message::Assistant { .. } => String::new(),
+    }
+}
+
+/// The message holds more than the text typed
+pub fn has_attachments(message: &Message) -> bool {
+    match message {
+        Message::User { content } => content.len() > 1,
+        Message::Assistant { .. } => false,
+    }
+}
+
+/// Path of an existing file mentioned in a sentence, maybe followed by punctuation
+fn mentioned_file(word: &str) -> Option<&str> {
+    [word, word.trim_end_matches(TRAILING_PUNCTUATION)]
+        .into_iter()
+        .find(|path| !path.is_empty() && Path::new(path).is_file())
+}
+
+fn attach(path: &Path, config: &AttachmentConfig) -> anyhow::Result<UserContent> {
+    if let Some(media_type) = image_type(path) {
+        let size = fs::metadata(path)?.len();
+        if size > config.max_image_bytes as u64 {
+            return Err(anyhow::anyhow!(
+                "Image {} is {size} bytes, more than the limit of {}",
+                path.display(),
+                config.max_image_bytes
+            ));
+        }
+
+        let data = fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+        return Ok(UserContent::image_base64(
+            STANDARD.encode(data),
+            Some(media_type),
+            None,
+        ));
+    }
+
+    let text = read_text(path, config.max_text_bytes)?;
+    Ok(UserContent::text(format!(
+        "<file path=\"{}\">\n{}\n</file>",
+        path.display(),
+        text.trim_end()
+    )))
+}
+
+fn image_type(path: &Path) -> Option<ImageMediaType> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match extension.as_str() {
+        "png" => Some(ImageMediaType::PNG),
+        "jpg" | "jpeg" => Some(ImageMediaType::JPEG),
+        "gif" => Some(ImageMediaType::GIF),
+        "webp" => Some(ImageMediaType::WEBP),
+        _ => None,
+    }
+}
+
+/// The first `max` bytes of a text file, with a note if it was cut
+fn read_text(path: &Path, max: usize) -> anyhow::Result<String> {
+    let not_text = || anyhow::anyhow!("{} is not a text file", path.display());
+
+    let size = fs::metadata(path)?.len();
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(max as u64).read_to_end(&mut bytes))
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(text) => text,
+        // The limit cut a character in two
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&bytes[..e.valid_up_to()]).map_err(|_| not_text())?
+        }
+        Err(_) => return Err(not_text()),
+    };
+    if text.contains('\0') {
+        return Err(not_text());
+    }
+
+    let mut text = text.to_string();
+    if size > max as u64 {
+        text.push_str(&format!("\n[cut after {max} of {size} bytes]"));
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Empty directory unique to the test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("whisper-attach-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn contents(message: &Message) -> Vec<UserContent> {
+        match message {
+            Message::User { content } => content.iter().cloned().collect(),
+            Message::Assistant { .. } => Vec::new(),
+        }
+    }
+
+    fn text(content: &UserContent) -> &str {
+        match content {
+            UserContent::Text(text) => &text.text,
+            other => panic!("not text: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn attaches_mentioned_files_once() {
+        let dir = temp_dir("mentions");
+        let file = dir.join("notes.txt");
+        fs::write(&file, "hello\n").unwrap();
+        let input = format!("Read @{0}, then @{0}. Ask @someone", file.display());
+
+        let message = prompt_message(&input, &AttachmentConfig::default()).unwrap();
+
+        let contents = contents(&message);
+        assert_eq!(contents.len(), 2);
+        assert_eq!(text(&contents[0]), input);
+        assert_eq!(
+            text(&contents[1]),
+            format!("<file path=\"{}\">\nhello\n</file>", file.display())
+        );
+        assert_eq!(prompt_text(&message), input);
+        assert!(has_attachments(&message));
+    }
+
+    #[test]
+    fn leaves_other_mentions_as_they_are() {
+        let message = prompt_message(
+            "Thanks @someone, see @missing.txt and me@example.com",
+            &AttachmentConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(contents(&message).len(), 1);
+        assert!(!has_attachments(&message));
+    }
+
+    #[test]
+    fn strips_the_punctuation_after_a_path() {
+        let dir = temp_dir("punctuation");
+        let file = dir.join("a.txt").display().to_string();
+        fs::write(&file, "text").unwrap();
+        // A name ending with a dot is kept whole
+        let dotted = dir.join("v1.").display().to_string();
+        fs::write(&dotted, "text").unwrap();
+
+        for suffix in ["", ",", ".", "?", ")", "\"", "!)."] {
+            let word = format!("{file}{suffix}");
+            assert_eq!(mentioned_file(&word), Some(file.as_str()), "{word}");
+        }
+        assert_eq!(mentioned_file(&dotted), Some(dotted.as_str()));
+        assert_eq!(mentioned_file(&format!("{file}x")), None);
+        assert_eq!(mentioned_file("."), None);
+    }
+
+    #[test]
+    fn cuts_long_text_files() {
+        let dir = temp_dir("cut");
+        let file = dir.join("long.txt");
+        fs::write(&file, "abcdef").unwrap();
+
+        assert_eq!(read_text(&file, 6).unwrap(), "abcdef");
+        assert_eq!(
+            read_text(&file, 4).unwrap(),
+            "abcd\n[cut after 4 of 6 bytes]"
+        );
+    }
+
+    #[test]
+    fn cuts_before_a_split_character() {
+        let dir = temp_dir("utf8");
+        let file = dir.join("accents.txt");
+        // `é` takes the bytes 1 and 2
+        fs::write(&file, "aéb").unwrap();
+
+        assert_eq!(read_text(&file, 2).unwrap(), "a\n[cut after 2 of 4 bytes]");
+        assert_eq!(read_text(&file, 3).unwrap(), "aé\n[cut after 3 of 4 bytes]");
+    }
+
+    #[test]
+    fn refuses_binary_files() {
+        let dir = temp_dir("binary");
+        let nul = dir.join("nul.txt");
+        fs::write(&nul, b"text\0more").unwrap();
+        let latin1 = dir.join("latin1.txt");
+        fs::write(&latin1, b"caf\xe9 au lait").unwrap();
+
+        assert!(read_text(&nul, 1024).is_err());
+        assert!(read_text(&latin1, 1024).is_err());
+        // The NUL is cut off
+        assert_eq!(
+            read_text(&nul, 4).unwrap(),
+            "text\n[cut after 4 of 9 bytes]"
+        );
+    }
+
+    #[test]
+    fn limits_the_size_of_images() {
+        let dir = temp_dir("image");
+        let image = dir.join("chart.PNG");
+        fs::write(&image, [0x89, b'P', b'N', b'G']).unwrap();
+        let config = AttachmentConfig {
+            max_image_bytes: 4,
+            ..Default::default()
+        };
+
+        match attach(&image, &config).unwrap() {
+            UserContent::Image(image) => {
+                assert_eq!(image.media_type, Some(ImageMediaType::PNG));
+            }
+            other => panic!("not an image: {other:?}"),
+        }
+
+        let config = AttachmentConfig {
+            max_image_bytes: 3,
+            ..Default::default()
+        };
+        let error = attach(&image, &config).unwrap_err().to_string();
+        assert!(
+            error.contains("4 bytes, more than the limit of 3"),
+            "{error}"
+        );
+    }
+}
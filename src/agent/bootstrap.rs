@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use rig::{client::completion::CompletionModelHandle, embeddings::tool::ToolSchema};
+
+use crate::{
+    agent::{
+        context::ContextManager,
+        model_adaptor::load_models,
+        profile::AgentFactory,
+        session::{AgentImpl, Session, SessionBuilder},
+        structured::OutputMode,
+        transcript::Transcript,
+        usage::{ModelPrice, UsageTracker},
+    },
+    config::read_config::{AppConfig, ChatConfig},
+    mcp::{
+        builtin::{self, BUILTIN_SERVER},
+        manager::{McpManager, McpManagerBuilder},
+    },
+    rag::{document_index::DocumentIndex, store, vector_index::VectorIndex},
+};
+
+/// Session running an agent built from a profile
+pub type ProfileSession = Session<AgentImpl<CompletionModelHandle<'static>>>;
+pub type ProfileSessionBuilder = SessionBuilder<AgentImpl<CompletionModelHandle<'static>>>;
+
+/// Everything loaded from config that a session needs
+pub struct Bootstrap {
+    pub factory: AgentFactory,
+    /// Knowledge base with the number of chunks retrieved per prompt
+    pub knowledge: Option<(usize, DocumentIndex)>,
+    /// Keep the MCP clients alive as long as the agents use their tools
+    pub mcp_manager: McpManager,
+    pub chat: ChatConfig,
+    /// Prices of the models by name
+    pub prices: HashMap<String, ModelPrice>,
+    /// Context windows of the models by name
+    pub context_windows: HashMap<String, usize>,
+    /// How the models are asked for structured output, by name
+    pub output_modes: HashMap<String, OutputMode>,
+}
+
+impl Bootstrap {
+    /// Load models, connect MCP servers and build the indexes.
+    ///
+    /// `model` overrides the model of every agent profile.
+    pub async fn load(app_config: &AppConfig, model: Option<&str>) -> anyhow::Result<Self> {
+        let (completion_models, embed_models) = load_models(app_config)?;
+
+        let profiles = app_config.agents.clone().unwrap_or_default();
+        let mut factory = AgentFactory::new(profiles, completion_models)
+            .vars(app_config.vars.clone())
+            .policy(app_config.proxy.policy.clone());
+        if let Some(model) = model {
+            factory = factory.override_model(model);
+        }
+
+        let mcp_manager = McpManagerBuilder::new()
+            .load_config(app_config.mcp_servers.as_ref().unwrap_or(&Vec::new()))
+            .build()
+            .await?;
+        let mut tools = mcp_manager.get_tools().await;
+
+        let builtin_tools = builtin::get_tools(&app_config.builtin_tools).await?;
+        if !builtin_tools.is_empty() {
+            if tools.contains_key(BUILTIN_SERVER) {
+                return Err(anyhow::anyhow!(
+                    "The MCP server name `{BUILTIN_SERVER}` is taken by the built-in tools"
+                ));
+            }
+            tools.insert(BUILTIN_SERVER.to_string(), builtin_tools);
+        }
+
+        let mut factory = factory.tools(tools);
+
+        let embed_model = embed_models.first().cloned();
+        let mut knowledge = None;
+
+        if let Some((embed_name, embed_model)) = embed_model {
+            let tool_store = store::open(&app_config.vector_store, "tools").await?;
+            let tool_index = VectorIndex::new(&embed_name, embed_model.clone(), tool_store);
+
+            let schemas = factory
+                .agent_tools()
+                .map(|tool| ToolSchema::try_from(tool))
+                .collect::<Result<Vec<_>, _>>()?;
+            // Without the index, the agents in dynamic mode get all their tools
+            match tool_index.sync_tools(schemas).await {
+                Ok(()) => factory = factory.tool_index(tool_index),
+                Err(e) => tracing::warn!("Failed to index the tools, send all tools instead: {e}"),
+            }
+
+            if let Some(knowledge_config) = &app_config.knowledge {
+                let document_store = store::open(&app_config.vector_store, "documents").await?;
+                let index =
+                    DocumentIndex::new(VectorIndex::new(embed_name, embed_model, document_store));
+
+                knowledge = Some((knowledge_config.samples, index));
+            }
+        } else {
+            tracing::warn!("No embedding model loaded, tool RAG and knowledge base are disabled");
+        }
+
+        Ok(Self {
+            factory,
+            knowledge,
+            mcp_manager,
+            chat: app_config.chat.clone(),
+            prices: app_config
+                .models
+                .iter()
+                .filter_map(|m| m.price.map(|price| (m.model_name.clone(), price)))
+                .collect(),
+            context_windows: app_config
+                .models
+                .iter()
+                .filter_map(|m| {
+                    m.context_window
+                        .map(|window| (m.model_name.clone(), window))
+                })
+                .collect(),
+            output_modes: app_config
+                .models
+                .iter()
+                .map(|m| {
+                    let mode = OutputMode::new(
+                        &m.provider,
+                        &m.base_url,
+                        &m.model_name,
+                        m.structured_output,
+                    );
+                    (m.model_name.clone(), mode)
+                })
+                .collect(),
+        })
+    }
+
+    /// Build a session running the agent of a profile, the default one if `None`
+    pub fn session(&self, agent: Option<&str>) -> anyhow::Result<ProfileSession> {
+        Ok(self
+            .session_builder(agent)?
+            .build()
+            .keep_interrupted(self.chat.keep_interrupted)
+            .usage_tracker(self.usage_tracker())
+            .context_manager(self.context_manager())
+            .attachments(self.chat.attachments.clone()))
+    }
+
+    /// Like [`Bootstrap::session`], but the session could still be customized
+    pub fn session_builder(&self, agent: Option<&str>) -> anyhow::Result<ProfileSessionBuilder> {
+        let profile = match agent {
+            Some(name) => self
+                .factory
+                .profile(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown agent `{name}`"))?,
+            None => self.factory.default_profile(),
+        };
+
+        let mut builder = SessionBuilder::new()
+            .agent(self.factory.build(profile)?)
+            .multi_turn_depth(profile.depth)
+            .show_usage()
+            .output_modes(self.output_modes.clone())
+            .profiles(self.factory.clone());
+
+        if let Some(model) = self.factory.model_name(profile) {
+            builder = builder.model_name(model);
+        }
+        if let Some((sample, index)) = &self.knowledge {
+            builder = builder.knowledge(*sample, index.clone());
+        }
+
+        Ok(builder)
+    }
+
+    /// Context manager of the configured models
+    pub fn context_manager(&self) -> ContextManager {
+        ContextManager::new(self.context_windows.clone(), self.chat.context.clone())
+    }
+
+    /// Transcript of an interactive session, saved if enabled. One-shot and
+    /// remote sessions keep theirs in memory only.
+    pub fn transcript(&self) -> Transcript {
+        if self.chat.session_dir.is_empty() {
+            Transcript::new()
+        } else {
+            Transcript::new().dir(&self.chat.session_dir)
+        }
+    }
+
+    /// Usage tracker pricing the configured models, logging if enabled
+    pub fn usage_tracker(&self) -> UsageTracker {
+        let tracker = UsageTracker::new(self.prices.clone());
+
+        if self.chat.usage_dir.is_empty() {
+            tracker
+        } else {
+            tracker.log_dir(&self.chat.usage_dir)
+        }
+    }
+}
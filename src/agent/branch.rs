@@ -0,0 +1,256 @@
+use rig::completion::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{oneshot::ToolCallRecord, usage::TokenUsage};
+
+/// Characters of a prompt shown in the list of turns
+const PREVIEW_CHARS: usize = 60;
+
+/// A prompt and its answer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Turn {
+    /// Turn answered just before, `None` for a first turn
+    pub parent: Option<usize>,
+    /// Text typed by the user
+    pub prompt: String,
+    /// Prompt sent with its attachments, if it has any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<Box<Message>>,
+    pub answer: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCallRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Model which answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Turn {
+    /// Prompt as sent to the model
+    pub fn message(&self) -> Message {
+        self.message
+            .as_deref()
+            .cloned()
+            .unwrap_or_else(|| Message::user(self.prompt.clone()))
+    }
+}
+
+/// Turns of a conversation as a tree, retrying or editing a turn starts a
+/// new branch next to it. Turns are identified by their index.
+#[derive(Debug, Default)]
+pub struct ConversationTree {
+    turns: Vec<Turn>,
+    /// Last turn of the active branch
+    current: Option<usize>,
+}
+
+impl ConversationTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild a tree, e.g. from an exported session
+    pub fn from_turns(turns: Vec<Turn>, current: Option<usize>) -> anyhow::Result<Self> {
+        for (id, turn) in turns.iter().enumerate() {
+            if let Some(parent) = turn.parent
+                && parent >= id
+            {
+                return Err(anyhow::anyhow!(
+                    "The parent {parent} of turn {id} does not come before it"
+                ));
+            }
+        }
+
+        let mut tree = Self {
+            turns,
+            current: None,
+        };
+        tree.set_current(current)?;
+        Ok(tree)
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.turns
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Turn> {
+        self.turns.get(id)
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Add a turn after the current one and make it current, return its id
+    pub fn push(&mut self, turn: Turn) -> usize {
+        self.turns.push(Turn {
+            parent: self.current,
+            ..turn
+        });
+
+        let id = self.turns.len() - 1;
+        self.current = Some(id);
+        id
+    }
+
+    /// Make `id` the last turn of the active branch, `None` before the first turn
+    pub fn set_current(&mut self, id: Option<usize>) -> anyhow::Result<()> {
+        if let Some(id) = id
+            && id >= self.turns.len()
+        {
+            return Err(anyhow::anyhow!("No turn {id}"));
+        }
+
+        self.current = id;
+        Ok(())
+    }
+
+    /// Ids of the turns from the first one to `id`
+    pub fn path(&self, id: Option<usize>) -> Vec<usize> {
+        let mut path = Vec::new();
+
+        let mut next = id;
+        while let Some(id) = next {
+            path.push(id);
+            next = self.turns[id].parent;
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// List the turns as an indented tree, the active branch is marked
+    pub fn render(&self) -> String {
+        let active = self.path(self.current);
+        let mut output = String::new();
+        self.render_children(None, 0, &active, &mut output);
+        output
+    }
+
+    fn render_children(
+        &self,
+        parent: Option<usize>,
+        depth: usize,
+        active: &[usize],
+        output: &mut String,
+    ) {
+        for (id, turn) in self.turns.iter().enumerate() {
+            if turn.parent != parent {
+                continue;
+            }
+
+            let marker = if Some(id) == self.current {
+                "●"
+            } else if active.contains(&id) {
+                "│"
+            } else {
+                " "
+            };
+            output.push_str(&format!(
+                "{marker} {}[{id}] {}\n",
+                "  ".repeat(depth),
+                preview(&turn.prompt)
+            ));
+
+            self.render_children(Some(id), depth + 1, active, output);
+        }
+    }
+}
+
+/// First line of a prompt, cut to fit in a list
+fn preview(prompt: &str) -> String {
+    let line = prompt.lines().next().unwrap_or_default();
+
+    match line.char_indices().nth(PREVIEW_CHARS) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(prompt: &str) -> Turn {
+        Turn {
+            prompt: prompt.to_string(),
+            answer: format!("answer to {prompt}"),
+            ..Default::default()
+        }
+    }
+
+    /// 0 -> 1 -> 2, and 3 retried after 0
+    fn tree() -> ConversationTree {
+        let mut tree = ConversationTree::new();
+        tree.push(turn("first"));
+        tree.push(turn("second"));
+        tree.push(turn("third"));
+        tree.set_current(Some(0)).unwrap();
+        tree.push(turn("second again"));
+        tree
+    }
+
+    #[test]
+    fn push_links_to_the_current_turn() {
+        let tree = tree();
+
+        assert_eq!(tree.current(), Some(3));
+        assert_eq!(tree.get(3).unwrap().parent, Some(0));
+        assert_eq!(tree.path(Some(2)), vec![0, 1, 2]);
+        assert_eq!(tree.path(Some(3)), vec![0, 3]);
+        assert_eq!(tree.path(None), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn set_current_checks_the_id() {
+        let mut tree = tree();
+
+        assert!(tree.set_current(Some(4)).is_err());
+        assert_eq!(tree.current(), Some(3));
+        tree.set_current(None).unwrap();
+        assert_eq!(tree.current(), None);
+    }
+
+    #[test]
+    fn rebuilds_from_turns() {
+        let turns = tree().turns().to_vec();
+
+        let tree = ConversationTree::from_turns(turns.clone(), Some(2)).unwrap();
+        assert_eq!(tree.path(tree.current()), vec![0, 1, 2]);
+
+        let mut cyclic = turns;
+        cyclic[1].parent = Some(1);
+        assert!(ConversationTree::from_turns(cyclic, None).is_err());
+        assert!(ConversationTree::from_turns(Vec::new(), Some(0)).is_err());
+    }
+
+    #[test]
+    fn renders_the_branches() {
+        let tree = tree();
+
+        let expected = concat!(
+            "│ [0] first\n",
+            "    [1] second\n",
+            "      [2] third\n",
+            "●   [3] second again\n",
+        );
+        assert_eq!(tree.render(), expected);
+    }
+
+    #[test]
+    fn previews_the_first_line() {
+        assert_eq!(preview("one\ntwo"), "one");
+        let long = "x".repeat(PREVIEW_CHARS + 5);
+        assert_eq!(preview(&long), format!("{}…", "x".repeat(PREVIEW_CHARS)));
+    }
+
+    #[test]
+    fn message_defaults_to_the_prompt() {
+        let turn = turn("hello");
+
+        assert_eq!(turn.message(), Message::user("hello"));
+    }
+}
@@ -1,36 +1,240 @@
-use crate::agent::session::{self, InputSource, ResponseSink};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use std::{io::IsTerminal, path::PathBuf};
 
-struct CliFrontend {
-    input: BufReader<tokio::io::Stdin>,
+use futures::FutureExt;
+
+use crate::agent::{
+    markdown::MarkdownRenderer,
+    session::{self, InputSource, ResponseSink},
+};
+use rustyline::{DefaultEditor, error::ReadlineError};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+/// Input history shared by the interactive frontends
+pub const DEFAULT_HISTORY_FILE: &str = ".whisper/history";
+
+const PROMPT: &str = "> ";
+/// Prompt of the following lines of a multi-line message
+const CONTINUATION_PROMPT: &str = "… ";
+const FENCE: &str = "```";
+
+/// Line based frontend on stdin and stdout.
+///
+/// A line ending with `\` continues on the next one, and a fenced code block
+/// is read until its closing fence. Answers are rendered as markdown when
+/// stdout is a terminal.
+pub struct CliFrontend {
+    /// Moved into a blocking thread while reading
+    editor: Option<DefaultEditor>,
+    history_file: Option<PathBuf>,
     output: BufWriter<tokio::io::Stdout>,
+    /// `None` writes the answers verbatim
+    markdown: Option<MarkdownRenderer>,
+    is_reasoning: bool,
+    /// Ctrl-C was pressed, another one at an empty prompt quits
+    pending_exit: bool,
+    /// Ctrl-C outside of the line editor. Kept as long as the frontend, as
+    /// the signal stays caught once listened to.
+    #[cfg(unix)]
+    interrupts: tokio::signal::unix::Signal,
+}
+
+/// Result of reading one line
+enum ReadLine {
+    Line(String),
+    /// Ctrl-C
+    Interrupted,
+    /// Ctrl-D or end of stdin
+    Eof,
+}
+
+impl CliFrontend {
+    pub fn new() -> Result<Self, session::SinkError> {
+        let editor = DefaultEditor::new().map_err(readline_error)?;
+
+        Ok(Self {
+            editor: Some(editor),
+            history_file: None,
+            output: BufWriter::new(tokio::io::stdout()),
+            markdown: std::io::stdout().is_terminal().then(MarkdownRenderer::new),
+            is_reasoning: false,
+            pending_exit: false,
+            #[cfg(unix)]
+            interrupts: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?,
+        })
+    }
+
+    /// Load the input history from `path` and append new inputs to it
+    pub fn history_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        if let Some(editor) = &mut self.editor
+            && path.exists()
+            && let Err(e) = editor.load_history(&path)
+        {
+            tracing::warn!(error=%e, path=%path.display(), "Failed to load input history");
+        }
+
+        self.history_file = Some(path);
+        self
+    }
+
+    async fn read_line(&mut self, prompt: &'static str) -> Result<ReadLine, session::SinkError> {
+        // Forget the Ctrl-C pressed while nothing could be stopped
+        #[cfg(unix)]
+        while self.interrupts.recv().now_or_never().is_some() {}
+
+        let mut editor = self
+            .editor
+            .take()
+            .ok_or_else(|| session::SinkError::Other("Line editor is unavailable".to_string()))?;
+
+        let (editor, result) = tokio::task::spawn_blocking(move || {
+            let result = editor.readline(prompt);
+            (editor, result)
+        })
+        .await
+        .map_err(|e| session::SinkError::Other(e.to_string()))?;
+        self.editor = Some(editor);
+
+        match result {
+            Ok(line) => Ok(ReadLine::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(ReadLine::Interrupted),
+            Err(ReadlineError::Eof) => Ok(ReadLine::Eof),
+            Err(e) => Err(readline_error(e)),
+        }
+    }
+
+    /// Read one message, which may span several lines. `None` at the end of input
+    async fn read_message(&mut self) -> Result<Option<String>, session::SinkError> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut in_fence = false;
+
+        loop {
+            let prompt = if lines.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            let line = match self.read_line(prompt).await? {
+                ReadLine::Line(line) => line,
+                ReadLine::Interrupted if lines.is_empty() && self.pending_exit => return Ok(None),
+                ReadLine::Interrupted if lines.is_empty() => {
+                    self.pending_exit = true;
+                    self.output
+                        .write_all(b"(press Ctrl-C again or Ctrl-D to quit)\n")
+                        .await?;
+                    self.output.flush().await?;
+                    continue;
+                }
+                // Drop the message being typed
+                ReadLine::Interrupted => {
+                    lines.clear();
+                    in_fence = false;
+                    continue;
+                }
+                ReadLine::Eof if lines.is_empty() => return Ok(None),
+                ReadLine::Eof => break,
+            };
+            self.pending_exit = false;
+
+            if line.matches(FENCE).count() % 2 == 1 {
+                in_fence = !in_fence;
+                lines.push(line);
+                if in_fence {
+                    continue;
+                }
+                break;
+            }
+
+            if in_fence {
+                lines.push(line);
+                continue;
+            }
+
+            match line.strip_suffix('\\') {
+                Some(line) => lines.push(line.to_string()),
+                None => {
+                    lines.push(line);
+                    break;
+                }
+            }
+        }
+
+        Ok(Some(lines.join("\n")))
+    }
+
+    /// Write the markdown still waiting for the end of its line or block
+    async fn flush_markdown(&mut self) -> Result<(), session::SinkError> {
+        if let Some(markdown) = &mut self.markdown {
+            let rest = markdown.finish();
+            self.output.write_all(rest.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn remember(&mut self, input: &str) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        if !matches!(editor.add_history_entry(input), Ok(true)) {
+            return;
+        }
+
+        let Some(path) = &self.history_file else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = editor.append_history(path) {
+            tracing::warn!(error=%e, path=%path.display(), "Failed to save input history");
+        }
+    }
+}
+
+fn readline_error(e: ReadlineError) -> session::SinkError {
+    match e {
+        ReadlineError::Io(e) => session::SinkError::Io(e),
+        e => session::SinkError::Other(e.to_string()),
+    }
 }
 
 impl InputSource for CliFrontend {
     async fn read_input(&mut self) -> Result<Option<String>, session::SinkError> {
-        let mut buf = String::new();
-        self.input.read_line(&mut buf).await?;
+        loop {
+            let Some(input) = self.read_message().await? else {
+                return Ok(None);
+            };
 
-        let line = buf.trim().to_string();
+            let input = input.trim();
+            if input.is_empty() {
+                continue;
+            }
+            if input == ":q" {
+                return Ok(None);
+            }
 
-        if line == ":q" {
-            return Ok(None);
+            self.remember(input);
+            return Ok(Some(input.to_string()));
         }
-
-        Ok(Some(line))
     }
 }
 
 impl ResponseSink for CliFrontend {
     async fn chat_start(&mut self) -> Result<(), session::SinkError> {
-        self.output.write_all(b"Enter `:q` to quit\n").await?;
+        self.output
+            .write_all(b"Enter `:q` or press Ctrl-D to quit, end a line with `\\` to go on\n")
+            .await?;
 
         Ok(())
     }
 
     async fn user_start(&mut self) -> Result<(), session::SinkError> {
+        self.flush_markdown().await?;
         self.output
-            .write_all(b"\n\x1b[1;32m\xF0\x9F\x98\x80 User: \x1b[0m\n> ")
+            .write_all(b"\n\x1b[1;32m\xF0\x9F\x98\x80 User: \x1b[0m\n")
             .await?;
         self.output.flush().await?;
 
@@ -39,7 +243,7 @@ impl ResponseSink for CliFrontend {
 
     async fn output_start(&mut self) -> Result<(), session::SinkError> {
         self.output
-            .write_all(b"\n\x1b[1;32m\xF0\x9F\x98\x80 User: \x1b[0m\n> ")
+            .write_all("\n\x1b[1;34m🤖 Assistant: \x1b[0m\n".as_bytes())
             .await?;
         self.output.flush().await?;
 
@@ -50,15 +254,19 @@ impl ResponseSink for CliFrontend {
         &mut self,
         content: &(dyn std::fmt::Display + Send + Sync),
     ) -> Result<(), session::SinkError> {
-        self.output
-            .write_all(content.to_string().as_bytes())
-            .await?;
+        let text = match &mut self.markdown {
+            Some(markdown) if !self.is_reasoning => markdown.push(&content.to_string()),
+            _ => content.to_string(),
+        };
+        self.output.write_all(text.as_bytes()).await?;
         self.output.flush().await?;
 
         Ok(())
     }
 
     async fn output_reason_start(&mut self) -> Result<(), session::SinkError> {
+        self.flush_markdown().await?;
+        self.is_reasoning = true;
         self.output
             .write_all("\n\x1b[1;90m🧠 Reasoning\n───────────────\n".as_bytes())
             .await?;
@@ -69,8 +277,9 @@ impl ResponseSink for CliFrontend {
 
     async fn output_reason_end(&mut self) -> Result<(), session::SinkError> {
         self.output
-            .write_all("\n────────────────\x1b[0m".as_bytes())
+            .write_all("\n────────────────\x1b[0m\n".as_bytes())
             .await?;
+        self.is_reasoning = false;
 
         self.output.flush().await?;
 
@@ -81,6 +290,7 @@ impl ResponseSink for CliFrontend {
         &mut self,
         usage: &Option<rig::completion::Usage>,
     ) -> Result<(), session::SinkError> {
+        self.flush_markdown().await?;
         self.output.write_all(b"\n").await?;
 
         if let Some(usage) = usage {
@@ -107,10 +317,50 @@ impl ResponseSink for CliFrontend {
         Ok(())
     }
 
+    async fn approve_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<bool, session::SinkError> {
+        self.flush_markdown().await?;
+        let call = format!("\n\x1b[1;33m🔧 {name}\x1b[0m {arguments}\n");
+        self.output.write_all(call.as_bytes()).await?;
+        self.output.flush().await?;
+
+        // Not added to the input history
+        let approved = match self.read_line("Allow this call? [y/N] ").await? {
+            ReadLine::Line(answer) => matches!(answer.trim(), "y" | "Y" | "yes"),
+            ReadLine::Interrupted | ReadLine::Eof => false,
+        };
+
+        Ok(approved)
+    }
+
+    async fn interrupted(&mut self) {
+        #[cfg(unix)]
+        if self.interrupts.recv().await.is_none() {
+            return std::future::pending().await;
+        }
+        // Without a handler Ctrl-C would kill the process
+        #[cfg(not(unix))]
+        if tokio::signal::ctrl_c().await.is_err() {
+            return std::future::pending().await;
+        }
+
+        self.pending_exit = true;
+        let _ = self.flush_markdown().await;
+        let _ = self
+            .output
+            .write_all(b"\n\x1b[1;33m\xE2\x8F\xB9 Interrupted\x1b[0m (Ctrl-C again to quit)\n")
+            .await;
+        let _ = self.output.flush().await;
+    }
+
     async fn output_error(
         &mut self,
         e: &(dyn std::fmt::Display + Send + Sync),
     ) -> Result<(), session::SinkError> {
+        self.flush_markdown().await?;
         self.output
             .write_all(b"\x1b[1;31m\xE2\x9D\x8C ERROR: \x1b[0m")
             .await?;
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+/// Slash commands typed at the prompt instead of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `/ingest <path>`: index local files into the knowledge base
+    Ingest(PathBuf),
+    /// `/agent [name]`: list the agent profiles, or switch to one
+    Agent(Option<String>),
+    /// `/usage`: show the tokens used and their estimated cost
+    Usage,
+    /// `/compact`: replace the history with a summary written by the model
+    Compact,
+    /// `/retry`: answer the last prompt again, on a new branch
+    Retry,
+    /// `/edit <message>`: replace the last prompt and answer it, on a new branch
+    Edit(String),
+    /// `/branch [turn]`: list the turns, or go on after another one
+    Branch(Option<usize>),
+    /// `/export <path>`: save the session as Markdown, HTML or JSON
+    Export(PathBuf),
+    /// A command that is unknown or has invalid arguments
+    Invalid(String),
+}
+
+impl Command {
+    /// Parse the input, return `None` if it is a normal query
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let rest = input.strip_prefix('/')?;
+
+        let (name, args) = match rest.split_once(char::is_whitespace) {
+            Some((name, args)) => (name, args.trim()),
+            None => (rest, ""),
+        };
+
+        let command = match name {
+            "ingest" if !args.is_empty() => Self::Ingest(PathBuf::from(args)),
+            "ingest" => Self::Invalid("Usage: /ingest <path>".to_string()),
+            "agent" if args.is_empty() => Self::Agent(None),
+            "agent" => Self::Agent(Some(args.to_string())),
+            "usage" => Self::Usage,
+            "compact" => Self::Compact,
+            "retry" => Self::Retry,
+            "edit" if !args.is_empty() => Self::Edit(args.to_string()),
+            "edit" => Self::Invalid("Usage: /edit <message>".to_string()),
+            "branch" if args.is_empty() => Self::Branch(None),
+            "branch" => match args.parse() {
+                Ok(turn) => Self::Branch(Some(turn)),
+                Err(_) => Self::Invalid("Usage: /branch [turn]".to_string()),
+            },
+            "export" if !args.is_empty() => Self::Export(PathBuf::from(args)),
+            "export" => Self::Invalid("Usage: /export <path.md|path.html|path.json>".to_string()),
+            _ => Self::Invalid(format!("Unknown command `/{name}`")),
+        };
+
+        Some(command)
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use rig::{
+    completion::Message,
+    message::{AssistantContent, UserContent},
+};
+use serde::{Deserialize, Serialize};
+
+/// Tokens added by the role and separators of every message
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Asked to the model to replace the oldest turns
+pub const SUMMARY_PROMPT: &str = "Summarize the conversation below for your own later reference. \
+Keep the facts, decisions, names, file paths and open questions, drop the small talk. \
+Answer with the summary only.";
+
+/// How the history is cut when it does not fit in the context window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Drop the oldest turns until the rest fits
+    #[default]
+    DropOldest,
+    /// Keep only the last `keep_turns` turns, fewer if they still do not fit
+    KeepLast,
+    /// Replace the oldest turns with a summary written by the model
+    Summarize,
+}
+
+/// Context budget of the sessions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ContextConfig {
+    #[serde(default)]
+    pub strategy: ContextStrategy,
+    /// Turns kept by `keep_last`
+    #[serde(default = "default_keep_turns")]
+    pub keep_turns: usize,
+    /// Tokens left for the answer, the tool definitions and the retrieved documents
+    #[serde(default = "default_reserve")]
+    pub reserve: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ContextStrategy::default(),
+            keep_turns: default_keep_turns(),
+            reserve: default_reserve(),
+        }
+    }
+}
+
+fn default_keep_turns() -> usize {
+    6
+}
+
+fn default_reserve() -> usize {
+    2048
+}
+
+/// Fit the history in the context window of the model answering
+#[derive(Debug, Clone, Default)]
+pub struct ContextManager {
+    /// Context window of the models by name, in tokens
+    windows: HashMap<String, usize>,
+    config: ContextConfig,
+}
+
+impl ContextManager {
+    pub fn new(windows: HashMap<String, usize>, config: ContextConfig) -> Self {
+        Self { windows, config }
+    }
+
+    pub fn strategy(&self) -> ContextStrategy {
+        self.config.strategy
+    }
+
+    /// Index of the first message to send, so that the history fits with
+    /// `fixed` tokens of preamble and prompt. If the window is unknown, only
+    /// `keep_last` cuts it.
+    ///
+    /// The history is only cut before a user message, so it never starts with
+    /// an answer or a tool result.
+    pub fn split(&self, model: Option<&str>, fixed: usize, chat_log: &[Message]) -> usize {
+        let turn_starts: Vec<usize> = chat_log
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| is_turn_start(message))
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut start = 0;
+        if self.config.strategy == ContextStrategy::KeepLast {
+            let skipped = turn_starts.len().saturating_sub(self.config.keep_turns);
+            start = turn_starts.get(skipped).copied().unwrap_or(chat_log.len());
+        }
+
+        let Some(window) = model.and_then(|model| self.windows.get(model)) else {
+            return start;
+        };
+
+        let available = window.saturating_sub(self.config.reserve + fixed);
+        let mut used: usize = chat_log[start..].iter().map(message_tokens).sum();
+        let first = start;
+        for &next in turn_starts.iter().filter(|index| **index > first) {
+            if used <= available {
+                break;
+            }
+            used -= chat_log[start..next]
+                .iter()
+                .map(message_tokens)
+                .sum::<usize>();
+            start = next;
+        }
+
+        if used > available {
+            chat_log.len()
+        } else {
+            start
+        }
+    }
+}
+
+/// A user message typed by the user, not a tool result
+fn is_turn_start(message: &Message) -> bool {
+    match message {
+        Message::User { content } => content
+            .iter()
+            .any(|content| !matches!(content, UserContent::ToolResult(_))),
+        Message::Assistant { .. } => false,
+    }
+}
+
+/// Rough number of tokens of a text: a token for about 4 ASCII characters,
+/// and one for every other character, which is closer for CJK text
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.bytes().filter(u8::is_ascii).count();
+    let others = text.chars().filter(|c| !c.is_ascii()).count();
+
+    ascii.div_ceil(4) + others
+}
+
+pub fn message_tokens(message: &Message) -> usize {
+    estimate_tokens(&message_text(message)) + MESSAGE_OVERHEAD
+}
+
+/// Text of a message, tool calls and results as JSON, media as a placeholder
+pub fn message_text(message: &Message) -> String {
+    let parts: Vec<String> = match message {
+        Message::User { content } => content
+            .iter()
+            .map(|content| match content {
+                UserContent::Text(text) => text.text.clone(),
+                UserContent::ToolResult(result) => {
+                    serde_json::to_string(&result.content).unwrap_or_default()
+                }
+                UserContent::Image(_) => "[image]".to_string(),
+                UserContent::Audio(_) => "[audio]".to_string(),
+                UserContent::Video(_) => "[video]".to_string(),
+                UserContent::Document(document) => document.data.to_string(),
+            })
+            .collect(),
+        Message::Assistant { content, .. } => content
+            .iter()
+            .map(|content| match content {
+                AssistantContent::Text(text) => text.text.clone(),
+                AssistantContent::ToolCall(call) => {
+                    format!("{}({})", call.function.name, call.function.arguments)
+                }
+                AssistantContent::Reasoning(reasoning) => reasoning.reasoning.concat(),
+            })
+            .collect(),
+    };
+
+    parts.join("\n")
+}
+
+/// The messages as a plain transcript, to be summarized
+pub fn plain_text(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let role = match message {
+                Message::User { .. } => "User",
+                Message::Assistant { .. } => "Assistant",
+            };
+            format!("{role}: {}", message_text(message))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Turn standing for the summarized ones, a user message followed by an
+/// answer so that the roles keep alternating
+pub fn summary_messages(summary: &str) -> [Message; 2] {
+    [
+        Message::user(format!(
+            "Summary of the earlier conversation:\n{}",
+            summary.trim()
+        )),
+        Message::assistant("Noted, I will go on from this summary."),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use rig::{
+        OneOrMany,
+        message::{ToolResult, ToolResultContent},
+    };
+
+    use super::*;
+
+    const MODEL: &str = "small";
+
+    fn manager(strategy: ContextStrategy, window: usize) -> ContextManager {
+        ContextManager::new(
+            HashMap::from([(MODEL.to_string(), window)]),
+            ContextConfig {
+                strategy,
+                keep_turns: 2,
+                reserve: 0,
+            },
+        )
+    }
+
+    /// Turns of a prompt and an answer of 40 characters, 14 tokens each
+    fn turns(count: usize) -> Vec<Message> {
+        (0..count)
+            .flat_map(|i| {
+                [
+                    Message::user(format!("{i:0>40}")),
+                    Message::assistant(format!("{i:0>40}")),
+                ]
+            })
+            .collect()
+    }
+
+    fn tool_result() -> Message {
+        Message::User {
+            content: OneOrMany::one(UserContent::ToolResult(ToolResult {
+                id: "call".to_string(),
+                call_id: None,
+                content: OneOrMany::one(ToolResultContent::text("42")),
+            })),
+        }
+    }
+
+    #[test]
+    fn estimates_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("日本語"), 3);
+        assert_eq!(message_tokens(&Message::user("abcd")), 1 + MESSAGE_OVERHEAD);
+    }
+
+    #[test]
+    fn keeps_everything_that_fits() {
+        let chat_log = turns(3);
+
+        assert_eq!(
+            manager(ContextStrategy::DropOldest, 1000).split(Some(MODEL), 10, &chat_log),
+            0
+        );
+    }
+
+    #[test]
+    fn drops_the_oldest_turns() {
+        let chat_log = turns(4);
+
+        // Two turns of 28 tokens fit beside the 10 fixed ones
+        let split = manager(ContextStrategy::DropOldest, 70).split(Some(MODEL), 10, &chat_log);
+
+        assert_eq!(split, 4);
+    }
+
+    #[test]
+    fn drops_everything_if_the_last_turn_does_not_fit() {
+        let chat_log = turns(2);
+
+        let split = manager(ContextStrategy::DropOldest, 30).split(Some(MODEL), 10, &chat_log);
+
+        assert_eq!(split, chat_log.len());
+    }
+
+    #[test]
+    fn unknown_window_keeps_everything() {
+        let chat_log = turns(4);
+        let manager = manager(ContextStrategy::DropOldest, 10);
+
+        assert_eq!(manager.split(Some("other"), 10, &chat_log), 0);
+        assert_eq!(manager.split(None, 10, &chat_log), 0);
+    }
+
+    #[test]
+    fn keep_last_applies_without_window() {
+        let chat_log = turns(5);
+        let manager = manager(ContextStrategy::KeepLast, 10);
+
+        assert_eq!(manager.split(None, 0, &chat_log), 6);
+        assert_eq!(manager.split(None, 0, &turns(2)), 0);
+    }
+
+    #[test]
+    fn keep_last_cuts_more_to_fit() {
+        let chat_log = turns(5);
+
+        let split = manager(ContextStrategy::KeepLast, 40).split(Some(MODEL), 0, &chat_log);
+
+        assert_eq!(split, 8);
+    }
+
+    #[test]
+    fn never_starts_with_a_tool_result() {
+        let mut chat_log = turns(1);
+        chat_log.extend([
+            Message::user("call the tool"),
+            Message::assistant("calling"),
+            tool_result(),
+            Message::assistant("the answer is 42"),
+        ]);
+
+        // Only the last turn fits, the tool result is kept with its prompt
+        let split = manager(ContextStrategy::DropOldest, 40).split(Some(MODEL), 0, &chat_log);
+
+        assert_eq!(split, 2);
+        assert!(is_turn_start(&chat_log[split]));
+    }
+
+    #[test]
+    fn summary_keeps_the_roles_alternating() {
+        let [user, assistant] = summary_messages("  the facts \n");
+
+        assert!(matches!(user, Message::User { .. }));
+        assert!(matches!(assistant, Message::Assistant { .. }));
+        assert_eq!(
+            message_text(&user),
+            "Summary of the earlier conversation:\nthe facts"
+        );
+    }
+}
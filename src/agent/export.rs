@@ -0,0 +1,594 @@
+use std::{fs, path::Path};
+
+use chrono::Local;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{
+    branch::{ConversationTree, Turn},
+    oneshot::ToolCallRecord,
+    usage::TokenUsage,
+};
+
+/// Version of the JSON format, raised on breaking changes
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Style of the HTML export
+const HTML_STYLE: &str = "
+body { max-width: 52rem; margin: 2rem auto; padding: 0 1rem; font: 16px/1.5 system-ui, sans-serif; color: #222; }
+h1 { font-size: 1.5rem; }
+.meta { color: #666; font-size: 0.9rem; }
+.turn { border-top: 1px solid #ddd; padding: 1rem 0; }
+.role { font-weight: bold; margin: 0.5rem 0; }
+.user { background: #f3f6fa; border-radius: 6px; padding: 0.5rem 1rem; }
+details { color: #555; margin: 0.5rem 0; }
+.tool { border-left: 3px solid #c90; padding-left: 0.75rem; margin: 0.5rem 0; }
+pre { background: #f6f6f6; padding: 0.5rem; overflow-x: auto; }
+code { font-family: ui-monospace, monospace; font-size: 0.9em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.25rem 0.5rem; }
+";
+
+/// A turn of an exported session with its id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTurn {
+    pub id: usize,
+    #[serde(flatten)]
+    pub turn: Turn,
+}
+
+/// A whole session, with all its branches, written by `/export`
+/// and read by `--resume`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    /// RFC 3339 local time
+    pub exported_at: String,
+    /// Model answering when exported
+    pub model: Option<String>,
+    /// Last turn of the active branch
+    pub current: Option<usize>,
+    /// Turns ordered by id
+    pub turns: Vec<ExportedTurn>,
+    /// Usage of all the requests of the session
+    pub usage: TokenUsage,
+}
+
+impl SessionExport {
+    pub fn new(tree: &ConversationTree, model: Option<&str>, usage: TokenUsage) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            exported_at: Local::now().to_rfc3339(),
+            model: model.map(str::to_string),
+            current: tree.current(),
+            turns: tree
+                .turns()
+                .iter()
+                .enumerate()
+                .map(|(id, turn)| ExportedTurn {
+                    id,
+                    turn: turn.clone(),
+                })
+                .collect(),
+            usage,
+        }
+    }
+
+    /// Rebuild the conversation tree of the session
+    pub fn tree(&self) -> anyhow::Result<ConversationTree> {
+        if self.version > EXPORT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported export version {}, expected at most {EXPORT_VERSION}",
+                self.version
+            ));
+        }
+
+        let mut turns = Vec::with_capacity(self.turns.len());
+        for (index, exported) in self.turns.iter().enumerate() {
+            if exported.id != index {
+                return Err(anyhow::anyhow!(
+                    "Turn {} found at position {index}, turns must be ordered by id",
+                    exported.id
+                ));
+            }
+            turns.push(exported.turn.clone());
+        }
+
+        ConversationTree::from_turns(turns, self.current)
+    }
+
+    /// The active branch as Markdown, reasoning folded in `<details>`
+    pub fn to_markdown(&self) -> anyhow::Result<String> {
+        let tree = self.tree()?;
+        let mut output = String::from("# Whisper session\n\n");
+        output.push_str(&format!("- Exported: {}\n", self.exported_at));
+        if let Some(model) = &self.model {
+            output.push_str(&format!("- Model: {model}\n"));
+        }
+        output.push_str(&format!("- Usage: {}\n", self.usage));
+
+        for id in tree.path(tree.current()) {
+            let Some(turn) = tree.get(id) else {
+                continue;
+            };
+
+            output.push_str(&format!("\n## Turn {id}\n\n### User\n\n{}\n", turn.prompt));
+
+            if !turn.reasoning.trim().is_empty() {
+                output.push_str(&format!(
+                    "\n<details>\n<summary>Reasoning</summary>\n\n{}\n\n</details>\n",
+                    turn.reasoning.trim()
+                ));
+            }
+            for call in &turn.tool_calls {
+                output.push_str(&format!(
+                    "\n**Tool call** `{}`\n\n{}",
+                    call.name,
+                    fenced("json", &pretty_arguments(call))
+                ));
+                if let Some(result) = &call.result {
+                    output.push_str(&format!("\nResult:\n\n{}", fenced("", result)));
+                }
+            }
+
+            output.push_str(&format!("\n### Assistant\n\n{}\n", turn.answer.trim()));
+            if let Some(footer) = turn_footer(turn) {
+                output.push_str(&format!("\n_{footer}_\n"));
+            }
+        }
+
+        if has_branches(&tree) {
+            output.push_str(&format!(
+                "\n## Branches\n\nOnly the active branch is shown above, all the turns are in the JSON export.\n\n{}",
+                fenced("", &tree.render())
+            ));
+        }
+
+        Ok(output)
+    }
+
+    /// The active branch as a standalone HTML page
+    pub fn to_html(&self) -> anyhow::Result<String> {
+        let tree = self.tree()?;
+        let mut body = String::from("<h1>Whisper session</h1>\n<p class=\"meta\">");
+        body.push_str(&format!("Exported {}", escape(&self.exported_at)));
+        if let Some(model) = &self.model {
+            body.push_str(&format!(" · {}", escape(model)));
+        }
+        body.push_str(&format!(" · {}</p>\n", escape(&self.usage.to_string())));
+
+        for id in tree.path(tree.current()) {
+            let Some(turn) = tree.get(id) else {
+                continue;
+            };
+
+            body.push_str(&format!(
+                "<div class=\"turn\" id=\"turn-{id}\">\n<div class=\"role\">User · turn {id}</div>\n<div class=\"user\">{}</div>\n",
+                markdown_to_html(&turn.prompt)
+            ));
+
+            if !turn.reasoning.trim().is_empty() {
+                body.push_str(&format!(
+                    "<details><summary>Reasoning</summary>\n{}</details>\n",
+                    markdown_to_html(&turn.reasoning)
+                ));
+            }
+            for call in &turn.tool_calls {
+                body.push_str(&format!(
+                    "<div class=\"tool\"><div>Tool call <code>{}</code></div>\n<pre><code>{}</code></pre>\n",
+                    escape(&call.name),
+                    escape(&pretty_arguments(call))
+                ));
+                if let Some(result) = &call.result {
+                    body.push_str(&format!(
+                        "<div>Result</div>\n<pre><code>{}</code></pre>\n",
+                        escape(result)
+                    ));
+                }
+                body.push_str("</div>\n");
+            }
+
+            body.push_str(&format!(
+                "<div class=\"role\">Assistant</div>\n{}",
+                markdown_to_html(&turn.answer)
+            ));
+            if let Some(footer) = turn_footer(turn) {
+                body.push_str(&format!("<p class=\"meta\">{}</p>\n", escape(&footer)));
+            }
+            body.push_str("</div>\n");
+        }
+
+        if has_branches(&tree) {
+            body.push_str(&format!(
+                "<div class=\"turn\"><div class=\"role\">Branches</div>\n<p class=\"meta\">Only the active branch is shown above, all the turns are in the JSON export.</p>\n<pre>{}</pre></div>\n",
+                escape(&tree.render())
+            ));
+        }
+
+        Ok(format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Whisper session</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n"
+        ))
+    }
+
+    /// Write the session, as Markdown, HTML or JSON after the extension of `path`
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let content = match extension.as_deref() {
+            Some("md" | "markdown") => self.to_markdown()?,
+            Some("html" | "htm") => self.to_html()?,
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown export format of {}, use .md, .html or .json",
+                    path.display()
+                ));
+            }
+        };
+
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+
+        Ok(())
+    }
+
+    /// Read a session exported as JSON
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let export: Self = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid session export {}: {e}", path.display()))?;
+
+        // Fail early on an inconsistent tree
+        export.tree()?;
+
+        Ok(export)
+    }
+}
+
+/// Some turns are not on the active branch
+fn has_branches(tree: &ConversationTree) -> bool {
+    tree.path(tree.current()).len() < tree.turns().len()
+}
+
+/// Model and usage of a turn, if known
+fn turn_footer(turn: &Turn) -> Option<String> {
+    let parts: Vec<String> = [
+        turn.model.clone(),
+        turn.usage.map(|usage| usage.to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+fn pretty_arguments(call: &ToolCallRecord) -> String {
+    serde_json::to_string_pretty(&call.arguments).unwrap_or_else(|_| call.arguments.to_string())
+}
+
+/// A code block whose fence is longer than any backtick run of `text`
+fn fenced(language: &str, text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{language}\n{}\n{fence}\n", text.trim_end())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Only web and mail links are kept, `javascript:` and the like are not
+fn safe_url(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    ["http", "https", "mailto"]
+        .iter()
+        .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+}
+
+/// Render markdown to HTML, raw HTML of the text is escaped
+fn markdown_to_html(text: &str) -> String {
+    let options =
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    let mut output = String::new();
+    let mut in_table_head = false;
+    // Whether each open link or image was rendered as a link
+    let mut links = Vec::new();
+
+    for event in Parser::new_ext(text, options) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => output.push_str("<p>"),
+                Tag::Heading { level, .. } => output.push_str(&format!("<{level}>")),
+                Tag::BlockQuote(_) => output.push_str("<blockquote>\n"),
+                Tag::CodeBlock(_) => output.push_str("<pre><code>"),
+                Tag::List(Some(1)) => output.push_str("<ol>\n"),
+                Tag::List(Some(start)) => output.push_str(&format!("<ol start=\"{start}\">\n")),
+                Tag::List(None) => output.push_str("<ul>\n"),
+                Tag::Item => output.push_str("<li>"),
+                Tag::Emphasis => output.push_str("<em>"),
+                Tag::Strong => output.push_str("<strong>"),
+                Tag::Strikethrough => output.push_str("<del>"),
+                // Images are linked, the page loads nothing from outside
+                Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                    let safe = safe_url(&dest_url);
+                    if safe {
+                        output.push_str(&format!("<a href=\"{}\">", escape(&dest_url)));
+                    }
+                    links.push(safe);
+                }
+                Tag::Table(_) => output.push_str("<table>\n"),
+                Tag::TableHead => {
+                    in_table_head = true;
+                    output.push_str("<thead><tr>");
+                }
+                Tag::TableRow => output.push_str("<tr>"),
+                Tag::TableCell if in_table_head => output.push_str("<th>"),
+                Tag::TableCell => output.push_str("<td>"),
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph => output.push_str("</p>\n"),
+                TagEnd::Heading(level) => output.push_str(&format!("</{level}>\n")),
+                TagEnd::BlockQuote(_) => output.push_str("</blockquote>\n"),
+                TagEnd::CodeBlock => output.push_str("</code></pre>\n"),
+                TagEnd::List(true) => output.push_str("</ol>\n"),
+                TagEnd::List(false) => output.push_str("</ul>\n"),
+                TagEnd::Item => output.push_str("</li>\n"),
+                TagEnd::Emphasis => output.push_str("</em>"),
+                TagEnd::Strong => output.push_str("</strong>"),
+                TagEnd::Strikethrough => output.push_str("</del>"),
+                // Pops the link whether it was rendered or not
+                TagEnd::Link | TagEnd::Image if links.pop() == Some(true) => {
+                    output.push_str("</a>")
+                }
+                TagEnd::Table => output.push_str("</tbody></table>\n"),
+                TagEnd::TableHead => {
+                    in_table_head = false;
+                    output.push_str("</tr></thead><tbody>\n");
+                }
+                TagEnd::TableRow => output.push_str("</tr>\n"),
+                TagEnd::TableCell if in_table_head => output.push_str("</th>"),
+                TagEnd::TableCell => output.push_str("</td>"),
+                _ => {}
+            },
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                output.push_str(&escape(&text))
+            }
+            Event::Code(code) => output.push_str(&format!("<code>{}</code>", escape(&code))),
+            Event::SoftBreak => output.push('\n'),
+            Event::HardBreak => output.push_str("<br>\n"),
+            Event::Rule => output.push_str("<hr>\n"),
+            Event::TaskListMarker(true) => output.push_str("☑ "),
+            Event::TaskListMarker(false) => output.push_str("☐ "),
+            _ => {}
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `first` answered, then retried as `retry`, `follow up` after the retry
+    fn tree() -> ConversationTree {
+        let mut tree = ConversationTree::new();
+        tree.push(Turn {
+            prompt: "first".to_string(),
+            answer: "one".to_string(),
+            ..Default::default()
+        });
+        tree.set_current(None).unwrap();
+        tree.push(Turn {
+            prompt: "retry".to_string(),
+            answer: "two".to_string(),
+            reasoning: "thinking".to_string(),
+            tool_calls: vec![ToolCallRecord {
+                name: "get_weather".to_string(),
+                arguments: serde_json::json!({ "city": "Paris" }),
+                result: Some("sunny".to_string()),
+            }],
+            model: Some("qwen3".to_string()),
+            ..Default::default()
+        });
+        tree.push(Turn {
+            prompt: "follow up".to_string(),
+            answer: "three".to_string(),
+            ..Default::default()
+        });
+        tree
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn keeps_only_web_and_mail_urls() {
+        assert!(safe_url("https://example.com"));
+        assert!(safe_url("HTTP://example.com"));
+        assert!(safe_url("mailto:someone@example.com"));
+        assert!(!safe_url("javascript:alert(1)"));
+        assert!(!safe_url("JavaScript:alert(1)"));
+        assert!(!safe_url("data:text/html,<script>"));
+        assert!(!safe_url("relative/path"));
+    }
+
+    #[test]
+    fn escapes_raw_html_of_the_text() {
+        let html = markdown_to_html("<script>alert(1)</script>\n\nsay <b onclick=\"x()\">hi</b>");
+
+        assert!(!html.contains("<script>"), "{html}");
+        assert!(!html.contains("<b "), "{html}");
+        assert!(
+            html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"),
+            "{html}"
+        );
+        assert!(
+            html.contains("say &lt;b onclick=&quot;x()&quot;&gt;hi&lt;/b&gt;"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn drops_unsafe_links_and_images() {
+        let html = markdown_to_html(
+            "[click](javascript:alert(1)) ![pixel](javascript:x) [ok](https://example.com) \
+             ![chart](https://example.com/c.png)",
+        );
+
+        assert_eq!(
+            html,
+            "<p>click pixel <a href=\"https://example.com\">ok</a> \
+             <a href=\"https://example.com/c.png\">chart</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn renders_markdown_blocks() {
+        let html =
+            markdown_to_html("# Title\n\n- **a**\n- `b`\n\n| x | y |\n|---|---|\n| 1 | 2 |\n");
+
+        assert_eq!(
+            html,
+            "<h1>Title</h1>\n<ul>\n<li><strong>a</strong></li>\n<li><code>b</code></li>\n</ul>\n\
+             <table>\n<thead><tr><th>x</th><th>y</th></tr></thead><tbody>\n\
+             <tr><td>1</td><td>2</td></tr>\n</tbody></table>\n"
+        );
+    }
+
+    #[test]
+    fn fence_is_longer_than_the_backtick_runs() {
+        assert_eq!(fenced("json", "{}"), "```json\n{}\n```\n");
+        assert_eq!(fenced("", "a ```` b\n"), "`````\na ```` b\n`````\n");
+
+        let text = "```rust\nfn main() {}\n```";
+        let block = fenced("", text);
+        let events: Vec<Event> = Parser::new(&block).collect();
+        assert!(
+            events.contains(&Event::Text(format!("{text}\n").into())),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn markdown_shows_the_active_branch() {
+        let export = SessionExport::new(&tree(), Some("qwen3"), TokenUsage::default());
+
+        let markdown = export.to_markdown().unwrap();
+
+        assert!(
+            markdown.contains("## Turn 1\n\n### User\n\nretry\n"),
+            "{markdown}"
+        );
+        assert!(
+            markdown.contains("**Tool call** `get_weather`"),
+            "{markdown}"
+        );
+        assert!(
+            markdown.contains("Result:\n\n```\nsunny\n```"),
+            "{markdown}"
+        );
+        assert!(markdown.contains("## Turn 2"), "{markdown}");
+        assert!(!markdown.contains("## Turn 0"), "{markdown}");
+        assert!(markdown.contains("## Branches"), "{markdown}");
+    }
+
+    #[test]
+    fn html_escapes_the_turns() {
+        let mut tree = ConversationTree::new();
+        tree.push(Turn {
+            prompt: "<img src=x onerror=alert(1)>".to_string(),
+            answer: "fine".to_string(),
+            tool_calls: vec![ToolCallRecord {
+                name: "<tool>".to_string(),
+                arguments: serde_json::json!({ "q": "</code>" }),
+                result: Some("<script>".to_string()),
+            }],
+            ..Default::default()
+        });
+        let export = SessionExport::new(&tree, Some("<model>"), TokenUsage::default());
+
+        let html = export.to_html().unwrap();
+
+        for raw in ["<img", "<tool>", "<script>", "<model>", "\"</code>\""] {
+            assert!(!html.contains(raw), "{raw} in {html}");
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let usage = TokenUsage {
+            input: 10,
+            output: 5,
+            cached: 2,
+        };
+        let export = SessionExport::new(&tree(), Some("qwen3"), usage);
+        let path =
+            std::env::temp_dir().join(format!("whisper-round-trip-{}.json", std::process::id()));
+
+        export.write(&path).unwrap();
+        let loaded = SessionExport::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.usage, usage);
+        assert_eq!(loaded.model.as_deref(), Some("qwen3"));
+        let tree = loaded.tree().unwrap();
+        assert_eq!(tree.current(), Some(2));
+        assert_eq!(tree.path(tree.current()), [1, 2]);
+        assert_eq!(tree.render(), self::tree().render());
+        let turn = tree.get(1).unwrap();
+        assert_eq!(turn.reasoning, "thinking");
+        assert_eq!(turn.tool_calls[0].result.as_deref(), Some("sunny"));
+        assert_eq!(turn.model.as_deref(), Some("qwen3"));
+    }
+
+    #[test]
+    fn refuses_inconsistent_exports() {
+        let mut export = SessionExport::new(&tree(), None, TokenUsage::default());
+        export.version = EXPORT_VERSION + 1;
+        assert!(export.tree().is_err());
+
+        let mut export = SessionExport::new(&tree(), None, TokenUsage::default());
+        export.turns.swap(0, 1);
+        assert!(export.tree().is_err());
+
+        let mut export = SessionExport::new(&tree(), None, TokenUsage::default());
+        export.current = Some(3);
+        assert!(export.tree().is_err());
+
+        assert!(export.write(Path::new("session.txt")).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::agent::session::{InputSource, ResponseSink, SinkError};
+
+/// One line written by [`JsonlSink`], tagged by `event`:
+///
+/// ```text
+/// {"event":"chat_start"}
+/// {"event":"user_start"}
+/// {"event":"output_start"}
+/// {"event":"text","text":"Hello"}
+/// {"event":"reasoning_start"}
+/// {"event":"reasoning_end"}
+/// {"event":"tool_call","name":"get_weather","arguments":{"city":"Paris"}}
+/// {"event":"finished","usage":{"input_tokens":12,"output_tokens":5,"total_tokens":17}}
+/// {"event":"error","message":"..."}
+/// {"event":"chat_finished"}
+/// ```
+///
+/// `text` events between `reasoning_start` and `reasoning_end` are reasoning,
+/// the others are the answer. `usage` of `finished` is `null` when unknown.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JsonlEvent<'a> {
+    ChatStart,
+    UserStart,
+    OutputStart,
+    Text {
+        text: &'a str,
+    },
+    ReasoningStart,
+    ReasoningEnd,
+    ToolCall {
+        name: &'a str,
+        arguments: &'a serde_json::Value,
+    },
+    Finished {
+        usage: &'a Option<Usage>,
+    },
+    Error {
+        message: &'a str,
+    },
+    ChatFinished,
+}
+
+/// One line read by [`JsonlFrontend`]: `{"prompt":"..."}`
+#[derive(Debug, Deserialize)]
+pub struct JsonlInput {
+    pub prompt: String,
+}
+
+/// Sink writing one [`JsonlEvent`] per line to any writer
+pub struct JsonlSink<W> {
+    output: W,
+}
+
+impl<W> JsonlSink<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+
+    async fn emit(&mut self, event: JsonlEvent<'_>) -> Result<(), SinkError> {
+        let mut line = serde_json::to_vec(&event).map_err(|e| SinkError::Output(e.to_string()))?;
+        line.push(b'\n');
+
+        self.output.write_all(&line).await?;
+        self.output.flush().await?;
+
+        Ok(())
+    }
+}
+
+impl<W> ResponseSink for JsonlSink<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    async fn chat_start(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::ChatStart).await
+    }
+
+    async fn user_start(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::UserStart).await
+    }
+
+    async fn output_start(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::OutputStart).await
+    }
+
+    async fn output_text(
+        &mut self,
+        content: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        let text = content.to_string();
+        self.emit(JsonlEvent::Text { text: &text }).await
+    }
+
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::ToolCall { name, arguments }).await
+    }
+
+    async fn output_reason_start(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::ReasoningStart).await
+    }
+
+    async fn output_reason_end(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::ReasoningEnd).await
+    }
+
+    async fn output_finished(&mut self, usage: &Option<Usage>) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::Finished { usage }).await
+    }
+
+    async fn chat_finished(&mut self) -> Result<(), SinkError> {
+        self.emit(JsonlEvent::ChatFinished).await
+    }
+
+    async fn output_error(
+        &mut self,
+        e: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        let message = e.to_string();
+        self.emit(JsonlEvent::Error { message: &message }).await
+    }
+}
+
+/// Frontend for programs, reading [`JsonlInput`] lines and writing [`JsonlEvent`] lines
+pub struct JsonlFrontend<R, W> {
+    input: R,
+    sink: JsonlSink<W>,
+}
+
+impl<R, W> JsonlFrontend<R, W>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            sink: JsonlSink::new(output),
+        }
+    }
+}
+
+impl<R, W> InputSource for JsonlFrontend<R, W>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    async fn read_input(&mut self) -> Result<Option<String>, SinkError> {
+        loop {
+            let mut buf = String::new();
+            if self.input.read_line(&mut buf).await? == 0 {
+                return Ok(None);
+            }
+
+            if buf.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<JsonlInput>(&buf) {
+                Ok(input) => return Ok(Some(input.prompt)),
+                // Report the bad line and wait for the next one
+                Err(e) => self.sink.output_error(&e).await?,
+            }
+        }
+    }
+}
+
+impl<R, W> ResponseSink for JsonlFrontend<R, W>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    async fn chat_start(&mut self) -> Result<(), SinkError> {
+        self.sink.chat_start().await
+    }
+
+    async fn user_start(&mut self) -> Result<(), SinkError> {
+        self.sink.user_start().await
+    }
+
+    async fn output_start(&mut self) -> Result<(), SinkError> {
+        self.sink.output_start().await
+    }
+
+    async fn output_text(
+        &mut self,
+        content: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.sink.output_text(content).await
+    }
+
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        self.sink.output_tool_call(name, arguments).await
+    }
+
+    async fn output_reason_start(&mut self) -> Result<(), SinkError> {
+        self.sink.output_reason_start().await
+    }
+
+    async fn output_reason_end(&mut self) -> Result<(), SinkError> {
+        self.sink.output_reason_end().await
+    }
+
+    async fn output_finished(&mut self, usage: &Option<Usage>) -> Result<(), SinkError> {
+        self.sink.output_finished(usage).await
+    }
+
+    async fn chat_finished(&mut self) -> Result<(), SinkError> {
+        self.sink.chat_finished().await
+    }
+
+    async fn output_error(
+        &mut self,
+        e: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.sink.output_error(e).await
+    }
+}
@@ -0,0 +1,161 @@
+use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+
+/// Default number of dimensions of the hashed vectors
+pub const DEFAULT_NDIMS: usize = 384;
+
+/// In-process embedding model based on feature hashing.
+///
+/// Every word and every character trigram of a word is hashed into a fixed
+/// size vector, which is then L2 normalized. It needs neither network nor
+/// model weights, so tool RAG keeps working offline.
+#[derive(Debug, Clone)]
+pub struct LocalEmbeddingModel {
+    ndims: usize,
+}
+
+impl Default for LocalEmbeddingModel {
+    fn default() -> Self {
+        Self::new(DEFAULT_NDIMS)
+    }
+}
+
+impl LocalEmbeddingModel {
+    pub fn new(ndims: usize) -> Self {
+        Self {
+            ndims: ndims.max(1),
+        }
+    }
+
+    /// Embed one text synchronously
+    pub fn embed(&self, text: &str) -> Vec<f64> {
+        let mut vec = vec![0.0; self.ndims];
+
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+        {
+            self.add_feature(&mut vec, word.as_bytes(), 1.0);
+
+            // Character trigrams make the model tolerant to inflections
+            let padded: Vec<char> = format!("#{word}#").chars().collect();
+            for gram in padded.windows(3) {
+                let gram: String = gram.iter().collect();
+                self.add_feature(&mut vec, gram.as_bytes(), 0.5);
+            }
+        }
+
+        let norm = vec.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vec.iter_mut().for_each(|v| *v /= norm);
+        }
+
+        vec
+    }
+
+    fn add_feature(&self, vec: &mut [f64], feature: &[u8], weight: f64) {
+        let hash = fnv1a(feature);
+        let index = (hash % self.ndims as u64) as usize;
+        // Use the highest bit as sign to reduce the bias of collisions
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+
+        vec[index] += sign * weight;
+    }
+}
+
+/// Stable hash, so vectors stay comparable across builds and launches
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    bytes
+        .iter()
+        .fold(OFFSET, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME))
+}
+
+impl EmbeddingModel for LocalEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|document| Embedding {
+                vec: self.embed(&document),
+                document,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cosine(a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn vectors_are_normalized() {
+        let model = LocalEmbeddingModel::default();
+
+        let vec = model.embed("Read a file from the disk");
+
+        assert_eq!(vec.len(), DEFAULT_NDIMS);
+        let norm = vec.iter().map(|v| v * v).sum::<f64>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_without_words_is_the_zero_vector() {
+        let vec = LocalEmbeddingModel::new(16).embed(" .,;! ");
+
+        assert_eq!(vec, vec![0.0; 16]);
+    }
+
+    #[test]
+    fn embedding_is_stable_and_ignores_case() {
+        let model = LocalEmbeddingModel::new(64);
+
+        assert_eq!(
+            model.embed("Weather Forecast"),
+            model.embed("weather forecast")
+        );
+        // Reference values of FNV-1a, another hash would break the stored vectors
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn related_texts_are_closer() {
+        let model = LocalEmbeddingModel::default();
+        let query = model.embed("what is the weather forecast tomorrow");
+
+        let weather = model.embed("Get the weather forecast of a city");
+        let files = model.embed("List the files of a directory");
+
+        assert!(cosine(&query, &weather) > cosine(&query, &files));
+    }
+
+    #[tokio::test]
+    async fn embeds_every_text() {
+        let model = LocalEmbeddingModel::new(8);
+
+        let embeddings = model
+            .embed_texts(vec!["one".to_string(), "two".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings[1].document, "two");
+        assert_eq!(embeddings[1].vec, model.embed("two"));
+    }
+}
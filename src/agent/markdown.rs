@@ -0,0 +1,528 @@
+use std::sync::LazyLock;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::as_24_bit_terminal_escaped,
+};
+use unicode_width::UnicodeWidthChar;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("base16-ocean.dark")
+        .unwrap_or_default()
+});
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const FENCE: &str = "```";
+
+/// What the previous lines opened
+enum Block {
+    Text,
+    Code(HighlightLines<'static>),
+    /// Rows of a table, rendered once the table ends to align the columns
+    Table(Vec<String>),
+}
+
+/// Render streamed markdown to ANSI text.
+///
+/// The words of a text line are written as they arrive, code blocks line by
+/// line and tables once their last row is known. Nothing already written is
+/// rendered again.
+pub struct MarkdownRenderer {
+    /// Text after the last newline
+    pending: String,
+    /// Bytes of `pending` already written
+    shown_len: usize,
+    /// How they were rendered, without the final reset
+    shown: String,
+    block: Block,
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownRenderer {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            shown_len: 0,
+            shown: String::new(),
+            block: Block::Text,
+        }
+    }
+
+    /// Add a chunk, return what can be rendered of it
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+
+        let mut output = String::new();
+        while let Some(index) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=index).collect();
+            self.render_line(line.trim_end_matches(['\n', '\r']), &mut output);
+        }
+        self.render_words(&mut output);
+
+        output
+    }
+
+    /// Write the complete words of the incomplete text line, up to the last
+    /// one which closes all its emphasis, code spans and links
+    fn render_words(&mut self, output: &mut String) {
+        if !matches!(self.block, Block::Text) {
+            return;
+        }
+        let trimmed = self.pending.trim_start();
+        // The line could still open a code block or a table
+        if trimmed.is_empty() || trimmed.starts_with('`') || trimmed.starts_with('|') {
+            return;
+        }
+
+        let Some((index, space)) = self
+            .pending
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+        else {
+            return;
+        };
+        let end = index + space.len_utf8();
+        let indent = self.pending.len() - trimmed.len();
+        if end <= indent || end <= self.shown_len || !is_balanced(&self.pending[..end]) {
+            return;
+        }
+
+        let rendered = render_text_line(&self.pending[..end]);
+        let rendered = rendered.strip_suffix(RESET).unwrap_or(&rendered);
+        if let Some(rest) = rendered.strip_prefix(self.shown.as_str()) {
+            output.push_str(rest);
+            self.shown = rendered.to_string();
+            self.shown_len = end;
+        }
+    }
+
+    /// Render what is left at the end of the message
+    pub fn finish(&mut self) -> String {
+        let mut output = String::new();
+
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.render_line(&line, &mut output);
+        }
+        match std::mem::replace(&mut self.block, Block::Text) {
+            Block::Table(rows) => render_table(&rows, &mut output),
+            Block::Code(_) => output.push_str(RESET),
+            Block::Text => {}
+        }
+
+        output
+    }
+
+    fn render_line(&mut self, line: &str, output: &mut String) {
+        match &mut self.block {
+            Block::Code(highlighter) => {
+                if line.trim_start().starts_with(FENCE) {
+                    output.push_str(&format!("{DIM}└───{RESET}\n"));
+                    self.block = Block::Text;
+                } else {
+                    output.push_str(&highlight(highlighter, line));
+                }
+                return;
+            }
+            Block::Table(rows) => {
+                if is_table_row(line) {
+                    rows.push(line.to_string());
+                    return;
+                }
+                let rows = std::mem::take(rows);
+                render_table(&rows, output);
+                self.block = Block::Text;
+            }
+            Block::Text => {}
+        }
+
+        let trimmed = line.trim_start();
+        if let Some(language) = trimmed.strip_prefix(FENCE) {
+            let language = language.trim();
+            let syntax = SYNTAXES
+                .find_syntax_by_token(language)
+                .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+
+            output.push_str(&format!("{DIM}┌─── {language}{RESET}\n"));
+            self.block = Block::Code(HighlightLines::new(syntax, &THEME));
+        } else if is_table_row(line) {
+            self.block = Block::Table(vec![line.to_string()]);
+        } else {
+            let shown = std::mem::take(&mut self.shown);
+            let shown_len = std::mem::take(&mut self.shown_len);
+            let rendered = render_text_line(line);
+            match rendered.strip_prefix(shown.as_str()) {
+                Some(rest) => output.push_str(rest),
+                // The end of the line changed its start, e.g. `* * *`
+                None => output.push_str(&render_inline(&line[shown_len..])),
+            }
+            output.push('\n');
+        }
+    }
+}
+
+fn highlight(highlighter: &mut HighlightLines<'static>, line: &str) -> String {
+    let line = format!("{line}\n");
+    match highlighter.highlight_line(&line, &SYNTAXES) {
+        Ok(ranges) => {
+            let escaped = as_24_bit_terminal_escaped(&ranges, false);
+            format!("{}{RESET}\n", escaped.trim_end_matches('\n'))
+        }
+        Err(_) => line,
+    }
+}
+
+/// No emphasis, code span or link is left open, so that rendering more of the
+/// line does not change the start
+fn is_balanced(text: &str) -> bool {
+    let count = |c: char| text.matches(c).count();
+
+    ['`', '*', '_', '~'].into_iter().all(|c| count(c) % 2 == 0)
+        && count('[') == count(']')
+        && count('(') == count(')')
+}
+
+fn is_table_row(line: &str) -> bool {
+    let line = line.trim();
+    line.len() > 1 && line.starts_with('|')
+}
+
+/// Render a line outside code blocks and tables
+fn render_text_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+        let color = if level == 1 { "\x1b[1;4;35m" } else { "\x1b[1;35m" };
+        return format!("{color}{}{RESET}", render_inline(trimmed[level..].trim()));
+    }
+
+    if trimmed.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|c| trimmed.chars().all(|t| t == *c || t == ' '))
+    {
+        return format!("{DIM}{}{RESET}", "─".repeat(40));
+    }
+
+    if let Some(quote) = trimmed.strip_prefix('>') {
+        return format!("{DIM}│{RESET} \x1b[3m{}{RESET}", render_inline(quote.trim()));
+    }
+
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(bullet) {
+            let item = match item.strip_prefix("[ ] ") {
+                Some(item) => format!("☐ {}", render_inline(item)),
+                None => match item.strip_prefix("[x] ") {
+                    Some(item) => format!("☑ {}", render_inline(item)),
+                    None => render_inline(item),
+                },
+            };
+            return format!("{indent}\x1b[33m•{RESET} {item}");
+        }
+    }
+
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0
+        && let Some(item) = trimmed[digits..].strip_prefix(". ")
+    {
+        return format!(
+            "{indent}\x1b[33m{}.{RESET} {}",
+            &trimmed[..digits],
+            render_inline(item)
+        );
+    }
+
+    format!("{indent}{}", render_inline(trimmed))
+}
+
+/// Render emphasis, inline code and links of one line
+fn render_inline(text: &str) -> String {
+    let mut output = String::new();
+    let mut link: Option<String> = None;
+
+    for event in Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Text(text) => output.push_str(&text),
+            Event::Code(code) => output.push_str(&format!("\x1b[36m{code}\x1b[39m")),
+            Event::Start(Tag::Strong) => output.push_str("\x1b[1m"),
+            Event::End(TagEnd::Strong) => output.push_str("\x1b[22m"),
+            Event::Start(Tag::Emphasis) => output.push_str("\x1b[3m"),
+            Event::End(TagEnd::Emphasis) => output.push_str("\x1b[23m"),
+            Event::Start(Tag::Strikethrough) => output.push_str("\x1b[9m"),
+            Event::End(TagEnd::Strikethrough) => output.push_str("\x1b[29m"),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                output.push_str("\x1b[4;34m");
+                link = Some(dest_url.to_string());
+            }
+            Event::End(TagEnd::Link) => {
+                output.push_str("\x1b[24;39m");
+                if let Some(url) = link.take() {
+                    output.push_str(&format!(" {DIM}({url}){RESET}"));
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => output.push(' '),
+            Event::Html(html) | Event::InlineHtml(html) => output.push_str(&html),
+            _ => {}
+        }
+    }
+
+    output
+}
+
+/// Render the rows of a table with aligned columns
+fn render_table(rows: &[String], output: &mut String) {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .filter(|row| !is_separator_row(row))
+        .map(|row| {
+            row.trim()
+                .trim_matches('|')
+                .split('|')
+                .map(|cell| render_inline(cell.trim()))
+                .collect()
+        })
+        .collect();
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| visible_width(cell))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let line: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{DIM}{left}{}{right}{RESET}\n", line.join(middle))
+    };
+
+    output.push_str(&border("┌", "┬", "┐"));
+    for (index, row) in rows.iter().enumerate() {
+        output.push_str(&format!("{DIM}│{RESET}"));
+        for (column, width) in widths.iter().enumerate() {
+            let cell = row.get(column).map(String::as_str).unwrap_or_default();
+            let padding = " ".repeat(width - visible_width(cell));
+            let cell = if index == 0 {
+                format!("\x1b[1m{cell}\x1b[22m")
+            } else {
+                cell.to_string()
+            };
+            output.push_str(&format!(" {cell}{padding} {DIM}│{RESET}"));
+        }
+        output.push('\n');
+
+        if index == 0 && rows.len() > 1 {
+            output.push_str(&border("├", "┼", "┤"));
+        }
+    }
+    output.push_str(&border("└", "┴", "┘"));
+}
+
+fn is_separator_row(row: &str) -> bool {
+    row.trim()
+        .chars()
+        .all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+/// Columns taken in the terminal, wide characters count twice and escape
+/// sequences not at all
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+
+    for c in text.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => in_escape = true,
+            (false, _) => width += c.width().unwrap_or(0),
+            (true, 'm') => in_escape = false,
+            (true, _) => {}
+        }
+    }
+
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render chunks as they would be streamed, then the end of the message
+    fn render(chunks: &[&str]) -> String {
+        let mut renderer = MarkdownRenderer::new();
+        let mut output: String = chunks.iter().map(|chunk| renderer.push(chunk)).collect();
+        output.push_str(&renderer.finish());
+        output
+    }
+
+    /// Text shown, without the escape sequences
+    fn plain(text: &str) -> String {
+        let mut plain = String::new();
+        let mut in_escape = false;
+        for c in text.chars() {
+            match (in_escape, c) {
+                (false, '\x1b') => in_escape = true,
+                (false, _) => plain.push(c),
+                (true, 'm') => in_escape = false,
+                (true, _) => {}
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn renders_headings() {
+        assert_eq!(
+            render(&["# Title\n## Part\n"]),
+            "\x1b[1;4;35mTitle\x1b[0m\n\x1b[1;35mPart\x1b[0m\n"
+        );
+        assert_eq!(render(&["#hashtag\n"]), "#hashtag\n");
+    }
+
+    #[test]
+    fn renders_lists() {
+        assert_eq!(
+            plain(&render(&[
+                "- one\n  * two\n- [ ] todo\n- [x] done\n3. three\n"
+            ])),
+            "• one\n  • two\n• ☐ todo\n• ☑ done\n3. three\n"
+        );
+        assert_eq!(render(&["- one\n"]), "\x1b[33m•\x1b[0m one\n");
+    }
+
+    #[test]
+    fn renders_inline_markup() {
+        assert_eq!(
+            render(&["**bold** `code` [site](https://example.com)\n"]),
+            "\x1b[1mbold\x1b[22m \x1b[36mcode\x1b[39m \x1b[4;34msite\x1b[24;39m \
+             \x1b[2m(https://example.com)\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn highlights_fenced_code() {
+        let output = render(&[
+            "```rust\n",
+            "let x = 1;\n",
+            "# not a heading\n",
+            "```\n",
+            "after\n",
+        ]);
+
+        assert!(
+            output.starts_with("\x1b[2m┌─── rust\x1b[0m\n"),
+            "{output:?}"
+        );
+        assert!(output.contains("\x1b[38;2;"), "{output:?}");
+        assert!(
+            output.ends_with("\x1b[2m└───\x1b[0m\nafter\n"),
+            "{output:?}"
+        );
+        assert_eq!(
+            plain(&output),
+            "┌─── rust\nlet x = 1;\n# not a heading\n└───\nafter\n"
+        );
+    }
+
+    #[test]
+    fn aligns_table_columns() {
+        let output = render(&[
+            "| a | long header |\n",
+            "|---|:---:|\n",
+            "| wide | x |\n",
+            "\n",
+        ]);
+
+        assert_eq!(
+            plain(&output),
+            "┌──────┬─────────────┐\n\
+             │ a    │ long header │\n\
+             ├──────┼─────────────┤\n\
+             │ wide │ x           │\n\
+             └──────┴─────────────┘\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn aligns_wide_characters() {
+        let output = render(&["| 名前 | ok |\n", "| ab | 🦀 |\n"]);
+
+        assert_eq!(
+            plain(&output),
+            "┌──────┬────┐\n\
+             │ 名前 │ ok │\n\
+             ├──────┼────┤\n\
+             │ ab   │ 🦀 │\n\
+             └──────┴────┘\n"
+        );
+    }
+
+    #[test]
+    fn writes_the_words_of_an_incomplete_line() {
+        let mut renderer = MarkdownRenderer::new();
+
+        assert_eq!(renderer.push("Hel"), "");
+        assert_eq!(renderer.push("lo wor"), "Hello");
+        assert_eq!(renderer.push("ld **bo"), " world");
+        assert_eq!(renderer.push("ld"), "");
+        assert_eq!(renderer.push("** and"), " \x1b[1mbold\x1b[22m");
+        assert_eq!(renderer.push(" more\n"), " and more\n");
+        assert_eq!(renderer.finish(), "");
+    }
+
+    #[test]
+    fn writes_a_streamed_heading_once() {
+        let chunks = ["# A ", "long ", "title\n", "- an ", "item\n"];
+
+        assert_eq!(render(&chunks), render(&[&chunks.concat()]));
+    }
+
+    #[test]
+    fn waits_for_lines_which_may_open_a_block() {
+        let mut renderer = MarkdownRenderer::new();
+
+        assert_eq!(renderer.push("``` "), "");
+        assert_eq!(renderer.push("\nfn main() {}"), "\x1b[2m┌─── \x1b[0m\n");
+        assert_eq!(plain(&renderer.push("\n")), "fn main() {}\n");
+
+        let mut renderer = MarkdownRenderer::new();
+        assert_eq!(renderer.push("| a | b "), "");
+    }
+
+    #[test]
+    fn finish_flushes_the_last_line_and_block() {
+        let mut renderer = MarkdownRenderer::new();
+        assert_eq!(renderer.push("the end"), "the");
+        assert_eq!(renderer.finish(), " end\n");
+
+        let mut renderer = MarkdownRenderer::new();
+        renderer.push("| a | b |\n| c | d |");
+        assert_eq!(
+            plain(&renderer.finish()),
+            "┌───┬───┐\n│ a │ b │\n├───┼───┤\n│ c │ d │\n└───┴───┘\n"
+        );
+
+        let mut renderer = MarkdownRenderer::new();
+        renderer.push("```\nunfinished\n");
+        assert_eq!(renderer.finish(), RESET);
+        assert_eq!(renderer.finish(), "");
+    }
+}
@@ -1,3 +1,21 @@
+pub mod approval;
+pub mod attachment;
+pub mod bootstrap;
+pub mod branch;
 pub mod cli_chat;
+pub mod command;
+pub mod context;
+pub mod export;
+pub mod jsonl;
+pub mod local_embedding;
+pub mod markdown;
 pub mod model_adaptor;
+pub mod oneshot;
+pub mod profile;
 pub mod session;
+pub mod structured;
+pub mod template;
+pub mod tool_log;
+pub mod transcript;
+pub mod tui;
+pub mod usage;
@@ -7,21 +7,27 @@ use rig::{
     providers::{deepseek, ollama, openai},
 };
 
-use crate::config::read_config::{AppConfig, ModelConfig, ModelType};
+use crate::{
+    agent::{
+        local_embedding::{self, LocalEmbeddingModel},
+        usage::MeteredModel,
+    },
+    config::read_config::{AppConfig, ModelConfig, ModelType},
+};
 
 trait ModelFactory {
     fn build_model(
         model_config: &ModelConfig,
-        embed_models: &mut Vec<Arc<dyn EmbeddingModelDyn>>,
-        completion_models: &mut Vec<Arc<dyn CompletionModelDyn>>,
+        embed_models: &mut EmbedModelVec,
+        completion_models: &mut CompletionModelVec,
     ) -> anyhow::Result<()>;
 }
 
 impl ModelFactory for openai::Client {
     fn build_model(
         model_config: &ModelConfig,
-        embed_models: &mut Vec<Arc<dyn EmbeddingModelDyn>>,
-        completion_models: &mut Vec<Arc<dyn CompletionModelDyn>>,
+        embed_models: &mut EmbedModelVec,
+        completion_models: &mut CompletionModelVec,
     ) -> anyhow::Result<()> {
         let client = openai::Client::builder(&model_config.api_key)
             .base_url(&model_config.base_url)
@@ -30,15 +36,17 @@ impl ModelFactory for openai::Client {
         match model_config.model_type {
             ModelType::Embedding => {
                 let embed = client.embedding_model(&model_config.model_name);
-                embed_models.push(Arc::new(embed));
+                embed_models.push((model_config.model_name.clone(), Arc::new(embed)));
             }
-            ModelType::Completion => {
+            ModelType::Completion | ModelType::Chat => {
                 let completion = client
                     .completion_model(&model_config.model_name)
                     .completions_api();
-                completion_models.push(Arc::new(completion));
+                completion_models.push((
+                    model_config.model_name.clone(),
+                    Arc::new(MeteredModel(completion)),
+                ));
             }
-            ModelType::Chat => {}
         }
 
         Ok(())
@@ -48,8 +56,8 @@ impl ModelFactory for openai::Client {
 impl ModelFactory for deepseek::Client {
     fn build_model(
         model_config: &ModelConfig,
-        _embed_models: &mut Vec<Arc<dyn EmbeddingModelDyn>>,
-        completion_models: &mut Vec<Arc<dyn CompletionModelDyn>>,
+        _embed_models: &mut EmbedModelVec,
+        completion_models: &mut CompletionModelVec,
     ) -> anyhow::Result<()> {
         let client = deepseek::Client::builder(&model_config.api_key)
             .base_url(&model_config.base_url)
@@ -57,11 +65,13 @@ impl ModelFactory for deepseek::Client {
 
         match model_config.model_type {
             ModelType::Embedding => {}
-            ModelType::Completion => {
+            ModelType::Completion | ModelType::Chat => {
                 let completion = client.completion_model(&model_config.model_name);
-                completion_models.push(Arc::new(completion));
+                completion_models.push((
+                    model_config.model_name.clone(),
+                    Arc::new(MeteredModel(completion)),
+                ));
             }
-            ModelType::Chat => {}
         }
 
         Ok(())
@@ -71,8 +81,8 @@ impl ModelFactory for deepseek::Client {
 impl ModelFactory for ollama::Client {
     fn build_model(
         model_config: &ModelConfig,
-        embed_models: &mut Vec<Arc<dyn EmbeddingModelDyn>>,
-        completion_models: &mut Vec<Arc<dyn CompletionModelDyn>>,
+        embed_models: &mut EmbedModelVec,
+        completion_models: &mut CompletionModelVec,
     ) -> anyhow::Result<()> {
         let client = ollama::Client::builder()
             .base_url(&model_config.base_url)
@@ -81,26 +91,53 @@ impl ModelFactory for ollama::Client {
         match model_config.model_type {
             ModelType::Embedding => {
                 let embed = client.embedding_model(&model_config.model_name);
-                embed_models.push(Arc::new(embed));
+                embed_models.push((model_config.model_name.clone(), Arc::new(embed)));
             }
-            ModelType::Completion => {
+            ModelType::Completion | ModelType::Chat => {
                 let completion = client.completion_model(&model_config.model_name);
-                completion_models.push(Arc::new(completion));
+                completion_models.push((
+                    model_config.model_name.clone(),
+                    Arc::new(MeteredModel(completion)),
+                ));
             }
-            ModelType::Chat => {}
         }
 
         Ok(())
     }
 }
 
-pub type CompletionModelVec = Vec<Arc<dyn CompletionModelDyn>>;
-pub type EmbedModelVec = Vec<Arc<dyn EmbeddingModelDyn>>;
+impl ModelFactory for LocalEmbeddingModel {
+    fn build_model(
+        model_config: &ModelConfig,
+        embed_models: &mut EmbedModelVec,
+        _completion_models: &mut CompletionModelVec,
+    ) -> anyhow::Result<()> {
+        match model_config.model_type {
+            ModelType::Embedding => {
+                let embed = LocalEmbeddingModel::new(
+                    model_config
+                        .dimensions
+                        .unwrap_or(local_embedding::DEFAULT_NDIMS),
+                );
+                embed_models.push((model_config.model_name.clone(), Arc::new(embed)));
+            }
+            ModelType::Completion | ModelType::Chat => {
+                anyhow::bail!("Provider `local` only supports embedding models");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Loaded models with their `model_name`
+pub type CompletionModelVec = Vec<(String, Arc<dyn CompletionModelDyn>)>;
+pub type EmbedModelVec = Vec<(String, Arc<dyn EmbeddingModelDyn>)>;
 
 fn load_models_for<T: ModelFactory>(
     model_config: &ModelConfig,
-    embed_models: &mut Vec<Arc<dyn EmbeddingModelDyn>>,
-    completion_models: &mut Vec<Arc<dyn CompletionModelDyn>>,
+    embed_models: &mut EmbedModelVec,
+    completion_models: &mut CompletionModelVec,
 ) -> anyhow::Result<()> {
     T::build_model(model_config, embed_models, completion_models)
 }
@@ -132,6 +169,13 @@ pub fn load_models(app_config: &AppConfig) -> anyhow::Result<(CompletionModelVec
                     &mut completion_models,
                 )?;
             }
+            "local" => {
+                load_models_for::<LocalEmbeddingModel>(
+                    model_config,
+                    &mut embed_models,
+                    &mut completion_models,
+                )?;
+            }
             _ => {}
         }
     }
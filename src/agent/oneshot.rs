@@ -0,0 +1,111 @@
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::session::{ResponseSink, SinkError};
+
+/// A tool call requested during the turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub name: String,
+    pub arguments: serde_json::Value,
+    /// Output of the tool, `None` if it was not run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+}
+
+/// Result of a one-shot turn, printed by `whisper ask --json`
+#[derive(Debug, Default, Serialize)]
+pub struct AskReport {
+    /// Final answer without reasoning
+    pub answer: String,
+    pub usage: Option<Usage>,
+    pub tool_calls: Vec<ToolCallRecord>,
+    pub errors: Vec<String>,
+}
+
+/// Sink collecting the answer instead of displaying it
+#[derive(Default)]
+pub struct CaptureSink {
+    report: AskReport,
+    is_reasoning: bool,
+}
+
+impl CaptureSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_report(self) -> AskReport {
+        self.report
+    }
+}
+
+impl ResponseSink for CaptureSink {
+    async fn chat_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn user_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_text(
+        &mut self,
+        content: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        if !self.is_reasoning {
+            self.report.answer.push_str(&content.to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        self.report.tool_calls.push(ToolCallRecord {
+            name: name.to_string(),
+            arguments: arguments.clone(),
+            result: None,
+        });
+
+        Ok(())
+    }
+
+    async fn output_reason_start(&mut self) -> Result<(), SinkError> {
+        self.is_reasoning = true;
+
+        Ok(())
+    }
+
+    async fn output_reason_end(&mut self) -> Result<(), SinkError> {
+        self.is_reasoning = false;
+
+        Ok(())
+    }
+
+    async fn output_finished(&mut self, usage: &Option<Usage>) -> Result<(), SinkError> {
+        self.report.usage = *usage;
+
+        Ok(())
+    }
+
+    async fn chat_finished(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_error(
+        &mut self,
+        e: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.report.errors.push(e.to_string());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use rig::{
+    agent::{Agent, AgentBuilder},
+    client::completion::CompletionModelHandle,
+    tool::{ToolDyn, ToolSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::{
+        approval::ApprovedTool, model_adaptor::CompletionModelVec, session::AgentProvider,
+        template::TemplateVars, tool_log::RecordedTool,
+    },
+    mcp::{
+        policy::{ApprovalPolicy, ToolPolicy},
+        tool_adaptor::McpToolAdaptor,
+    },
+    rag::vector_index::VectorIndex,
+};
+
+/// Agent built from a profile, the model is chosen at runtime
+pub type DynAgent = Agent<CompletionModelHandle<'static>>;
+
+const DEFAULT_PREAMBLE: &str = "You are a helpful assistant.
+When answering questions, first write out your reasoning step by step,
+then give the final concise answer.  Keep the explanation short but clear.
+";
+
+/// How the MCP tools are given to the model
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolMode {
+    /// Every tool is sent with each request
+    All,
+    /// Only the tools most relevant to the prompt, needs an embedding model
+    #[default]
+    Dynamic,
+    /// No tool at all
+    None,
+}
+
+/// A named agent defined by `[[agents]]` in config
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AgentProfile {
+    pub name: String,
+    /// `model_name` of a completion model, the first loaded one if missing
+    pub model: Option<String>,
+    /// Template rendered when the agent is built, see [`TemplateVars`]
+    pub preamble: Option<String>,
+    /// Read the preamble from this file, takes precedence over `preamble`
+    pub preamble_file: Option<PathBuf>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    /// Maximum number of tool calling rounds for one prompt
+    #[serde(default = "default_depth")]
+    pub depth: usize,
+    /// Names of the MCP servers whose tools are allowed, `builtin` for the
+    /// built-in tools, all of them if missing
+    pub mcp_servers: Option<Vec<String>>,
+    #[serde(default)]
+    pub tool_mode: ToolMode,
+    /// Number of tools selected in dynamic mode
+    #[serde(default = "default_tool_samples")]
+    pub tool_samples: usize,
+}
+
+fn default_depth() -> usize {
+    4
+}
+
+fn default_tool_samples() -> usize {
+    2
+}
+
+impl Default for AgentProfile {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            model: None,
+            preamble: Some(DEFAULT_PREAMBLE.to_string()),
+            preamble_file: None,
+            temperature: Some(0.6),
+            max_tokens: None,
+            depth: default_depth(),
+            mcp_servers: None,
+            tool_mode: ToolMode::default(),
+            tool_samples: default_tool_samples(),
+        }
+    }
+}
+
+impl AgentProfile {
+    fn load_preamble(&self) -> anyhow::Result<Option<String>> {
+        match &self.preamble_file {
+            Some(path) => std::fs::read_to_string(path).map(Some).map_err(|e| {
+                anyhow::anyhow!("Failed to read preamble file {}: {e}", path.display())
+            }),
+            None => Ok(self.preamble.clone()),
+        }
+    }
+}
+
+/// Build agents from profiles, models and MCP tools
+#[derive(Clone)]
+pub struct AgentFactory {
+    profiles: Vec<AgentProfile>,
+    models: CompletionModelVec,
+    /// Tools grouped by MCP server name
+    tools: HashMap<String, Vec<McpToolAdaptor>>,
+    /// Tools with their server, sorted by server name, a name taken by an
+    /// earlier server is dropped
+    ordered_tools: Vec<(String, McpToolAdaptor)>,
+    /// Index of all tools, required by [`ToolMode::Dynamic`]
+    tool_index: Option<VectorIndex>,
+    /// Custom variables of the preambles
+    vars: HashMap<String, String>,
+    /// Which tools the agents may call and which need the user's approval
+    policy: ToolPolicy,
+}
+
+impl AgentFactory {
+    /// Use the default profile if `profiles` is empty
+    pub fn new(profiles: Vec<AgentProfile>, models: CompletionModelVec) -> Self {
+        let profiles = if profiles.is_empty() {
+            vec![AgentProfile::default()]
+        } else {
+            profiles
+        };
+
+        Self {
+            profiles,
+            models,
+            tools: HashMap::new(),
+            ordered_tools: Vec::new(),
+            tool_index: None,
+            vars: HashMap::new(),
+            policy: ToolPolicy::default(),
+        }
+    }
+
+    /// Make every profile use the model named `model`
+    pub fn override_model(mut self, model: &str) -> Self {
+        for profile in &mut self.profiles {
+            profile.model = Some(model.to_string());
+        }
+        self
+    }
+
+    pub fn tools(mut self, tools: HashMap<String, Vec<McpToolAdaptor>>) -> Self {
+        let mut servers: Vec<_> = tools.iter().collect();
+        servers.sort_by_key(|(server, _)| *server);
+
+        let mut names = HashSet::new();
+        self.ordered_tools = Vec::new();
+        for (server, server_tools) in servers {
+            for tool in server_tools {
+                let name = tool.name();
+                if !names.insert(name.clone()) {
+                    tracing::warn!(
+                        server,
+                        tool = %name,
+                        "A tool of another server has the same name, this one is dropped"
+                    );
+                    continue;
+                }
+                self.ordered_tools.push((server.clone(), tool.clone()));
+            }
+        }
+
+        self.tools = tools;
+        self
+    }
+
+    pub fn tool_index(mut self, tool_index: VectorIndex) -> Self {
+        self.tool_index = Some(tool_index);
+        self
+    }
+
+    /// Custom variables of the preamble templates
+    pub fn vars(mut self, vars: HashMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Hide the denied tools and ask the user before the calls it requires
+    pub fn policy(mut self, policy: ToolPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// MCP tools grouped by server name
+    pub fn mcp_tools(&self) -> &HashMap<String, Vec<McpToolAdaptor>> {
+        &self.tools
+    }
+
+    /// The tools given to the agents, in a stable order with unique names
+    pub fn agent_tools(&self) -> impl Iterator<Item = &McpToolAdaptor> {
+        self.ordered_tools.iter().map(|(_, tool)| tool)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&AgentProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// The profile used when none is given
+    pub fn default_profile(&self) -> &AgentProfile {
+        &self.profiles[0]
+    }
+
+    /// Name of the model used by a profile
+    pub fn model_name<'a>(&'a self, profile: &'a AgentProfile) -> Option<&'a str> {
+        profile
+            .model
+            .as_deref()
+            .or_else(|| self.models.first().map(|(name, _)| name.as_str()))
+    }
+
+    /// Build the agent of a profile
+    pub fn build(&self, profile: &AgentProfile) -> anyhow::Result<DynAgent> {
+        let model = match &profile.model {
+            Some(name) => self
+                .models
+                .iter()
+                .find(|(model_name, _)| model_name == name)
+                .ok_or_else(|| anyhow::anyhow!("Model `{name}` is not loaded"))?,
+            None => self
+                .models
+                .first()
+                .ok_or_else(|| anyhow::anyhow!("No completion model is loaded"))?,
+        };
+
+        let mut builder = AgentBuilder::new(CompletionModelHandle {
+            inner: model.1.clone(),
+        })
+        .name(&profile.name);
+
+        let tools: Vec<AgentTool> = self
+            .ordered_tools
+            .iter()
+            .filter(|(server, _)| {
+                profile
+                    .mcp_servers
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.contains(server))
+            })
+            .filter_map(
+                |(server, tool)| match self.policy.policy(server, &tool.tool().name) {
+                    ApprovalPolicy::Deny => None,
+                    policy => Some(RecordedTool(ApprovedTool::new(tool.clone(), policy))),
+                },
+            )
+            .collect();
+
+        let mode = match (profile.tool_mode, &self.tool_index) {
+            (ToolMode::Dynamic, None) => {
+                tracing::warn!("No tool index for dynamic tools, send all tools instead");
+                ToolMode::All
+            }
+            (mode, _) => mode,
+        };
+
+        if let Some(preamble) = profile.load_preamble()? {
+            let tools = match mode {
+                ToolMode::None => String::new(),
+                _ => tool_list(&tools),
+            };
+            let vars = TemplateVars::from_env()
+                .set("agent", &profile.name)
+                .set("model", &model.0)
+                .set("tools", tools)
+                .extend(&self.vars);
+            builder = builder.preamble(&vars.render(&preamble));
+        }
+
+        if let Some(temperature) = profile.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(max_tokens) = profile.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+
+        let agent = match mode {
+            ToolMode::Dynamic => {
+                // The index holds the tools of every server, only those of
+                // the agent may be found
+                let index = self
+                    .tool_index
+                    .clone()
+                    .expect("checked above")
+                    .only(tools.iter().map(|t| t.name()).collect());
+                builder
+                    .dynamic_tools(profile.tool_samples, index, ToolSet::from_tools(tools))
+                    .build()
+            }
+            ToolMode::All => {
+                let mut agent = builder.build();
+                agent.static_tools = tools.iter().map(|t| t.name()).collect();
+                agent.tools = Arc::new(ToolSet::from_tools(tools));
+                agent
+            }
+            ToolMode::None => builder.build(),
+        };
+
+        Ok(agent)
+    }
+}
+
+/// MCP tool as given to the agents
+type AgentTool = RecordedTool<ApprovedTool<McpToolAdaptor>>;
+
+/// One line per tool, with its description, for the `tools` variable
+fn tool_list(tools: &[AgentTool]) -> String {
+    tools
+        .iter()
+        .map(|RecordedTool(tool)| {
+            let tool = tool.inner().tool();
+            match tool.description.as_deref() {
+                Some(description) => format!("- {}: {description}", tool.name),
+                None => format!("- {}", tool.name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl AgentProvider<CompletionModelHandle<'static>> for AgentFactory {
+    fn names(&self) -> Vec<String> {
+        self.profiles.iter().map(|p| p.name.clone()).collect()
+    }
+
+    fn build_agent(&self, name: &str) -> anyhow::Result<(DynAgent, usize)> {
+        let profile = self
+            .profile(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown agent `{name}`"))?;
+
+        Ok((self.build(profile)?, profile.depth))
+    }
+
+    fn model_name(&self, name: &str) -> Option<String> {
+        let profile = self.profile(name)?;
+        AgentFactory::model_name(self, profile).map(str::to_string)
+    }
+}
@@ -2,12 +2,33 @@ use futures::StreamExt;
 use rig::{
     agent::{Agent, MultiTurnStreamItem, Text},
     completion::{Chat, CompletionModel, Message, Usage},
-    message::{Reasoning, ToolCall},
+    message::{AssistantContent, Reasoning, ToolCall},
     streaming::{StreamedAssistantContent, StreamingPrompt},
 };
 
-use std::io;
+use std::{collections::HashMap, io, sync::Arc};
 use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::{
+    agent::{
+        approval::{ApprovalRequest, approving},
+        attachment::{AttachmentConfig, has_attachments, prompt_message, prompt_text},
+        branch::{ConversationTree, Turn},
+        command::Command,
+        context::{
+            ContextManager, ContextStrategy, SUMMARY_PROMPT, estimate_tokens, message_tokens,
+            plain_text, summary_messages,
+        },
+        export::SessionExport,
+        oneshot::ToolCallRecord,
+        structured::{self, OutputMode, check_answer, retry_prompt},
+        tool_log::recorded,
+        transcript::{Transcript, TranscriptEntry},
+        usage::{TokenUsage, UsageTracker, metered},
+    },
+    rag::document_index::DocumentIndex,
+};
 
 /// Unified error type for ResponseSink
 #[derive(Debug, Error)]
@@ -39,16 +60,55 @@ where
     M: CompletionModel + 'static,
 {
     agent: Agent<M>,
+    /// Name of the model, to price the usage
+    model: Option<String>,
     multi_turn_depth: usize,
     show_usage: bool,
-    usage: Usage,
+    usage: TokenUsage,
+    knowledge: Option<(usize, DocumentIndex)>,
+    profiles: Option<Box<dyn AgentProvider<M>>>,
+    /// How the models are asked for structured output, by name
+    output_modes: HashMap<String, OutputMode>,
 }
 
+/// Appended to the preamble when a knowledge base is attached
+const CITE_PREAMBLE: &str = "
+When you use the attached documents, cite them by their id (`path:start-end`).
+";
+
 pub struct SessionBuilder<T>(T);
 
-pub struct Session<T>(T);
+pub struct Session<T> {
+    inner: T,
+    /// Messages sent with every request
+    chat_log: Vec<Message>,
+    /// Keep the partial answer of an interrupted request in the history
+    keep_interrupted: bool,
+    usage: UsageTracker,
+    context: ContextManager,
+    /// The whole conversation, `chat_log` may be cut or compacted
+    transcript: Transcript,
+    tree: ConversationTree,
+    /// Turns at the end of `chat_log` kept verbatim, the others were
+    /// compacted or given with the history
+    verbatim: usize,
+    attachments: AttachmentConfig,
+    /// Attach the files mentioned as `@path`, off for prompts written by programs
+    mentions: bool,
+}
+
+/// Answer of a request
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    pub text: String,
+    /// The user stopped the answer, `text` is partial
+    pub interrupted: bool,
+    pub reasoning: String,
+    pub tool_calls: Vec<ToolCallRecord>,
+}
 
 /// Trait to abstract display
+#[allow(async_fn_in_trait)]
 pub trait ResponseSink {
     /// Output the string to indicate the start of the chat
     async fn chat_start(&mut self) -> Result<(), SinkError>;
@@ -63,6 +123,16 @@ pub trait ResponseSink {
         content: &(dyn std::fmt::Display + Send + Sync),
     ) -> Result<(), SinkError>;
 
+    /// Output a tool call requested by the assistant
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        let call_msg = format!("Call function {name} with arguments {arguments}...");
+        self.output_text(&call_msg).await
+    }
+
     /// Output the start of reasoning content
     async fn output_reason_start(&mut self) -> Result<(), SinkError>;
     /// Output the end of reasoning content
@@ -78,32 +148,110 @@ pub trait ResponseSink {
         &mut self,
         e: &(dyn std::fmt::Display + Send + Sync),
     ) -> Result<(), SinkError>;
+
+    /// Ask the user to allow a tool call, refused by default as there is
+    /// nobody to ask
+    async fn approve_tool_call(
+        &mut self,
+        _name: &str,
+        _arguments: &serde_json::Value,
+    ) -> Result<bool, SinkError> {
+        Ok(false)
+    }
+
+    /// Resolve when the user stops the answer, never by default.
+    ///
+    /// Polled while waiting for the model, so it must be cancel safe.
+    async fn interrupted(&mut self) {
+        std::future::pending().await
+    }
+}
+
+/// Build agents by profile name, used to switch agent mid-session
+pub trait AgentProvider<M>: Send + Sync
+where
+    M: CompletionModel,
+{
+    /// Names of all profiles
+    fn names(&self) -> Vec<String>;
+
+    /// Build the agent of a profile, with its multi turn depth
+    fn build_agent(&self, name: &str) -> anyhow::Result<(Agent<M>, usize)>;
+
+    /// Name of the model used by a profile
+    fn model_name(&self, _name: &str) -> Option<String> {
+        None
+    }
 }
 
 /// Trait to abstract get input
+#[allow(async_fn_in_trait)]
 pub trait InputSource {
     async fn read_input(&mut self) -> Result<Option<String>, SinkError>;
 }
 
 /// Trait to abstract message behavior
-trait ChatSession {
+#[allow(async_fn_in_trait)]
+pub trait ChatSession {
     /// Send request and display the streaming answer within response sink
     async fn request<S: ResponseSink>(
         &mut self,
-        prompt: &str,
+        prompt: Message,
         chat_log: Vec<Message>,
         sink: &mut S,
-    ) -> anyhow::Result<String>;
+    ) -> anyhow::Result<Reply>;
 
     /// Show usage or not
     fn show_usage(&self) -> bool {
         false
     }
 
-    /// Get the usage
-    fn usage(&self) -> Option<Usage> {
+    /// Get the usage of the last request
+    fn usage(&self) -> Option<TokenUsage> {
+        None
+    }
+
+    /// Name of the model answering
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// System prompt sent with every request
+    fn preamble(&self) -> Option<&str> {
+        None
+    }
+
+    /// Ask for an answer matching a JSON schema, return it as is
+    async fn structured(
+        &mut self,
+        _prompt: Message,
+        _chat_log: Vec<Message>,
+        _schema: &serde_json::Value,
+    ) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!(
+            "This session could not give structured output"
+        ))
+    }
+
+    /// Ask the model to summarize a transcript, without tools nor preamble
+    async fn summarize(&mut self, _transcript: &str) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!("This session could not summarize"))
+    }
+
+    /// Get the knowledge base attached as dynamic context
+    fn knowledge(&self) -> Option<&DocumentIndex> {
         None
     }
+
+    /// Names of the agents could be switched to
+    fn agents(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Switch to another agent, the chat history is kept
+    fn switch_agent(&mut self, _name: &str) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("This session could not switch agent"))
+    }
 }
 
 /// Could only chat with assistant.
@@ -113,14 +261,25 @@ where
 {
     async fn request<S: ResponseSink>(
         &mut self,
-        prompt: &str,
+        prompt: Message,
         chat_log: Vec<Message>,
         sink: &mut S,
-    ) -> anyhow::Result<String> {
-        let res = self.0.chat(prompt, chat_log).await?;
-        sink.output_text(&res);
+    ) -> anyhow::Result<Reply> {
+        let res = tokio::select! {
+            res = self.0.chat(prompt, chat_log) => res?,
+            _ = sink.interrupted() => {
+                return Ok(Reply {
+                    interrupted: true,
+                    ..Default::default()
+                });
+            }
+        };
+        sink.output_text(&res).await?;
 
-        Ok(res)
+        Ok(Reply {
+            text: res,
+            ..Default::default()
+        })
     }
 }
 
@@ -131,10 +290,125 @@ where
 {
     async fn request<S: ResponseSink>(
         &mut self,
-        prompt: &str,
+        prompt: Message,
         chat_log: Vec<Message>,
         sink: &mut S,
+    ) -> anyhow::Result<Reply> {
+        let (approver, mut approvals) = mpsc::unbounded_channel();
+        let request = self.stream_request(prompt, chat_log, sink, &mut approvals);
+        let ((reply, tool_calls), usage) = metered(recorded(approving(approver, request))).await;
+        self.usage = usage;
+
+        let mut reply = reply?;
+        reply.tool_calls = tool_calls;
+        Ok(reply)
+    }
+
+    fn show_usage(&self) -> bool {
+        self.show_usage
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        Some(self.usage)
+    }
+
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    fn preamble(&self) -> Option<&str> {
+        self.agent.preamble.as_deref()
+    }
+
+    async fn structured(
+        &mut self,
+        prompt: Message,
+        chat_log: Vec<Message>,
+        schema: &serde_json::Value,
     ) -> anyhow::Result<String> {
+        let mode = self
+            .model
+            .as_ref()
+            .and_then(|model| self.output_modes.get(model))
+            .unwrap_or(&OutputMode::Tool);
+
+        let (answer, usage) = metered(structured::request(
+            &self.agent,
+            mode,
+            prompt,
+            chat_log,
+            schema,
+        ))
+        .await;
+        self.usage = usage;
+
+        answer
+    }
+
+    async fn summarize(&mut self, transcript: &str) -> anyhow::Result<String> {
+        let response = self
+            .agent
+            .model
+            .completion_request(Message::user(format!("{SUMMARY_PROMPT}\n\n{transcript}")))
+            .send()
+            .await?;
+
+        let summary: String = response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect();
+        if summary.trim().is_empty() {
+            return Err(anyhow::anyhow!("The model returned an empty summary"));
+        }
+
+        Ok(summary)
+    }
+
+    fn knowledge(&self) -> Option<&DocumentIndex> {
+        self.knowledge.as_ref().map(|(_, index)| index)
+    }
+
+    fn agents(&self) -> Vec<String> {
+        self.profiles
+            .as_ref()
+            .map(|profiles| profiles.names())
+            .unwrap_or_default()
+    }
+
+    fn switch_agent(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(profiles) = &self.profiles else {
+            return Err(anyhow::anyhow!("No agent profiles provided"));
+        };
+
+        let (mut agent, multi_turn_depth) = profiles.build_agent(name)?;
+        if let Some((sample, index)) = &self.knowledge {
+            attach_knowledge(&mut agent, *sample, index.clone());
+        }
+
+        self.agent = agent;
+        self.model = profiles.model_name(name);
+        self.multi_turn_depth = multi_turn_depth;
+
+        Ok(())
+    }
+}
+
+impl<M> AgentImpl<M>
+where
+    M: CompletionModel + 'static,
+{
+    /// Stream the answer to the sink, the usage is taken by the caller
+    async fn stream_request<S: ResponseSink>(
+        &mut self,
+        prompt: Message,
+        chat_log: Vec<Message>,
+        sink: &mut S,
+        approvals: &mut mpsc::UnboundedReceiver<ApprovalRequest>,
+    ) -> anyhow::Result<Reply> {
         let mut response_stream = self
             .agent
             .stream_prompt(prompt)
@@ -142,12 +416,31 @@ where
             .multi_turn(self.multi_turn_depth)
             .await;
 
-        let mut acc = String::new();
+        let mut reply = Reply::default();
 
         let mut is_reasoning = false;
         loop {
-            let Some(chunk) = response_stream.next().await else {
-                break Ok(acc);
+            // Dropping the stream also drops the pending tool calls
+            let chunk = tokio::select! {
+                chunk = response_stream.next() => chunk,
+                // A tool call of the stream waits for the user
+                Some(request) = approvals.recv() => {
+                    let approved = sink
+                        .approve_tool_call(&request.name, &request.arguments)
+                        .await?;
+                    request.answer(approved);
+                    continue;
+                }
+                _ = sink.interrupted() => {
+                    if is_reasoning {
+                        sink.output_reason_end().await?;
+                    }
+                    reply.interrupted = true;
+                    break;
+                }
+            };
+            let Some(chunk) = chunk else {
+                break;
             };
 
             // Process every kind of chunk
@@ -169,8 +462,10 @@ where
                         continue;
                     }
 
-                    if !is_reasoning {
-                        acc.push_str(&text);
+                    if is_reasoning {
+                        reply.reasoning.push_str(&text);
+                    } else {
+                        reply.text.push_str(&text);
                     }
                     sink.output_text(&text).await?;
                 }
@@ -180,6 +475,7 @@ where
                 ))) => {
                     let reasoning = reasoning.join("\n");
 
+                    reply.reasoning.push_str(&reasoning);
                     sink.output_reason_start().await?;
                     sink.output_text(&reasoning).await?;
                     sink.output_reason_end().await?;
@@ -188,34 +484,49 @@ where
                 Ok(MultiTurnStreamItem::StreamItem(StreamedAssistantContent::ToolCall(
                     ToolCall { function, .. },
                 ))) => {
-                    let call_msg = format!(
-                        "Call function {} with arguments {}...",
-                        function.name, function.arguments
-                    );
-
-                    acc.push_str(&call_msg);
-                    sink.output_text(&call_msg).await?;
-                }
-                // Final
-                Ok(MultiTurnStreamItem::FinalResponse(r)) => {
-                    if self.show_usage {
-                        self.usage = r.usage();
-                    }
+                    // Recorded in `reply.tool_calls` once the call is done
+                    sink.output_tool_call(&function.name, &function.arguments)
+                        .await?;
                 }
                 Err(e) => {
-                    sink.output_error(&e);
+                    sink.output_error(&e).await?;
                 }
                 _ => {}
             }
         }
-    }
 
-    fn show_usage(&self) -> bool {
-        self.show_usage
+        Ok(reply)
     }
+}
 
-    fn usage(&self) -> Option<Usage> {
-        Some(self.usage)
+/// Add the knowledge base to the dynamic context of an agent.
+///
+/// Return false if the agent is shared and could not be modified.
+fn attach_knowledge<M>(agent: &mut Agent<M>, sample: usize, index: DocumentIndex) -> bool
+where
+    M: CompletionModel,
+{
+    let Some(dynamic_context) = Arc::get_mut(&mut agent.dynamic_context) else {
+        return false;
+    };
+
+    dynamic_context.push((sample, Box::new(index)));
+    agent
+        .preamble
+        .get_or_insert_default()
+        .push_str(CITE_PREAMBLE);
+
+    true
+}
+
+/// Run `work` unless the user interrupts it first
+async fn interruptible<S: ResponseSink, T>(
+    sink: &mut S,
+    work: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::select! {
+        output = work => Some(output),
+        _ = sink.interrupted() => None,
     }
 }
 
@@ -242,9 +553,13 @@ impl SessionBuilder<NoImplProvided> {
     ) -> SessionBuilder<AgentImpl<M>> {
         SessionBuilder(AgentImpl {
             agent,
+            model: None,
             multi_turn_depth: 1,
             show_usage: false,
-            usage: Usage::default(),
+            usage: TokenUsage::default(),
+            knowledge: None,
+            profiles: None,
+            output_modes: HashMap::new(),
         })
     }
 
@@ -260,7 +575,7 @@ where
 {
     pub fn build(self) -> Session<ChatImpl<T>> {
         let SessionBuilder(chat_impl) = self;
-        Session(chat_impl)
+        Session::new(chat_impl)
     }
 }
 
@@ -275,6 +590,14 @@ where
         })
     }
 
+    /// Name of the model of the agent, to price the usage
+    pub fn model_name(self, model: impl Into<String>) -> Self {
+        SessionBuilder(AgentImpl {
+            model: Some(model.into()),
+            ..self.0
+        })
+    }
+
     pub fn show_usage(self) -> Self {
         SessionBuilder(AgentImpl {
             show_usage: true,
@@ -282,8 +605,48 @@ where
         })
     }
 
+    /// Append instructions to the preamble of the agent
+    pub fn append_preamble(self, text: &str) -> Self {
+        let mut agent_impl = self.0;
+
+        let preamble = agent_impl.agent.preamble.get_or_insert_default();
+        preamble.push('\n');
+        preamble.push_str(text);
+
+        SessionBuilder(agent_impl)
+    }
+
+    /// Attach a knowledge base, `sample` chunks are retrieved for every prompt
+    pub fn knowledge(self, sample: usize, index: DocumentIndex) -> Self {
+        let mut agent_impl = self.0;
+
+        if attach_knowledge(&mut agent_impl.agent, sample, index.clone()) {
+            agent_impl.knowledge = Some((sample, index));
+        } else {
+            tracing::warn!("Agent is shared, could not attach knowledge base");
+        }
+
+        SessionBuilder(agent_impl)
+    }
+
+    /// How the models are asked for structured output, by name
+    pub fn output_modes(self, output_modes: HashMap<String, OutputMode>) -> Self {
+        SessionBuilder(AgentImpl {
+            output_modes,
+            ..self.0
+        })
+    }
+
+    /// Allow switching agent with `/agent <name>`
+    pub fn profiles(self, profiles: impl AgentProvider<M> + 'static) -> Self {
+        SessionBuilder(AgentImpl {
+            profiles: Some(Box::new(profiles)),
+            ..self.0
+        })
+    }
+
     pub fn build(self) -> Session<AgentImpl<M>> {
-        Session(self.0)
+        Session::new(self.0)
     }
 }
 
@@ -291,18 +654,121 @@ impl<T> Session<T>
 where
     T: ChatSession,
 {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            chat_log: Vec::new(),
+            keep_interrupted: true,
+            usage: UsageTracker::default(),
+            context: ContextManager::default(),
+            transcript: Transcript::default(),
+            tree: ConversationTree::new(),
+            verbatim: 0,
+            attachments: AttachmentConfig::default(),
+            mentions: true,
+        }
+    }
+
+    /// Continue a conversation, e.g. sent by a client with each request
+    pub fn with_history(mut self, chat_log: Vec<Message>) -> Self {
+        self.chat_log = chat_log;
+        self
+    }
+
+    /// Whether an interrupted answer stays in the history, true by default
+    pub fn keep_interrupted(mut self, keep: bool) -> Self {
+        self.keep_interrupted = keep;
+        self
+    }
+
+    /// Track the usage with prices and a log
+    pub fn usage_tracker(mut self, usage: UsageTracker) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Fit the history in the context window before each request
+    pub fn context_manager(mut self, context: ContextManager) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Keep the whole conversation, e.g. saved to a file
+    pub fn transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = transcript;
+        self
+    }
+
+    /// Limits of the files attached to the prompts
+    pub fn attachments(mut self, attachments: AttachmentConfig) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    /// Whether `@path` in the prompts attaches the file
+    pub fn attach_mentions(mut self, mentions: bool) -> Self {
+        self.mentions = mentions;
+        self
+    }
+
+    /// Go on with an exported session, its active branch becomes the history
+    pub fn resume(mut self, export: &SessionExport) -> anyhow::Result<Self> {
+        let tree = export.tree()?;
+        let path = tree.path(tree.current());
+
+        self.chat_log.clear();
+        for id in &path {
+            if let Some(turn) = tree.get(*id) {
+                self.chat_log.push(turn.message());
+                self.chat_log.push(Message::assistant(turn.answer.clone()));
+            }
+        }
+        self.verbatim = path.len();
+
+        for (id, turn) in tree.turns().iter().enumerate() {
+            self.transcript.push(TranscriptEntry::Turn {
+                id,
+                turn: turn.clone(),
+            });
+        }
+        if tree
+            .current()
+            .is_some_and(|current| current + 1 != tree.turns().len())
+        {
+            self.transcript.push(TranscriptEntry::Checkout {
+                turn: tree.current(),
+            });
+        }
+
+        self.tree = tree;
+        Ok(self)
+    }
+
+    /// The whole session with its branches, to be written by [`SessionExport::write`]
+    pub fn export(&self) -> SessionExport {
+        SessionExport::new(&self.tree, self.inner.model(), self.usage.total())
+    }
+
     pub async fn run<S>(mut self, sink: &mut S) -> anyhow::Result<()>
     where
         S: ResponseSink + InputSource,
     {
-        let mut chat_log = vec![];
+        sink.chat_start().await?;
         loop {
             sink.user_start().await?;
 
             if let Some(input) = sink.read_input().await? {
-                let response = self.0.request(&input, chat_log.clone(), sink).await?;
-                chat_log.push(Message::user(input));
-                chat_log.push(Message::assistant(response));
+                if let Some(command) = Command::parse(&input) {
+                    self.execute(command, sink).await?;
+                    continue;
+                }
+
+                match self.prompt(&input) {
+                    Ok(prompt) => {
+                        self.turn(prompt, sink).await?;
+                    }
+                    Err(e) => sink.output_error(&e).await?,
+                }
             } else {
                 break;
             }
@@ -312,4 +778,472 @@ where
 
         Ok(())
     }
+
+    /// Message of a prompt typed by the user, with the `@path` files attached
+    /// unless mentions are off
+    pub fn prompt(&self, input: &str) -> anyhow::Result<Message> {
+        if !self.mentions {
+            return Ok(Message::user(input));
+        }
+        prompt_message(input, &self.attachments)
+    }
+
+    /// Answer a single prompt after the history, without any input source
+    pub async fn ask<S: ResponseSink>(
+        &mut self,
+        prompt: impl Into<Message>,
+        sink: &mut S,
+    ) -> anyhow::Result<String> {
+        Ok(self.turn(prompt.into(), sink).await?.text)
+    }
+
+    /// Answer `prompt` with JSON matching `schema` after the history and add
+    /// the turn. Invalid answers are sent back with their errors at most
+    /// `retries` times.
+    pub async fn ask_structured(
+        &mut self,
+        prompt: impl Into<Message>,
+        schema: &serde_json::Value,
+        retries: usize,
+    ) -> anyhow::Result<serde_json::Value> {
+        let validator = structured::validator(schema)?;
+        let prompt = prompt.into();
+        let mut history = self.history(&prompt).await;
+        let mut request = prompt.clone();
+
+        let mut errors = Vec::new();
+        for _ in 0..=retries {
+            let answer = self
+                .inner
+                .structured(request.clone(), history.clone(), schema)
+                .await?;
+
+            match check_answer(&validator, &answer) {
+                Ok(value) => {
+                    let reply = Reply {
+                        text: answer,
+                        ..Default::default()
+                    };
+                    self.record(prompt, &reply);
+                    return Ok(value);
+                }
+                Err(found) => {
+                    tracing::warn!(errors = ?found, "The answer does not match the schema");
+                    self.record_usage();
+
+                    history.push(request);
+                    history.push(Message::assistant(answer));
+                    request = Message::user(retry_prompt(&found));
+                    errors = found;
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "No answer matched the schema, the last one has these errors:\n{}",
+            errors.join("\n")
+        ))
+    }
+
+    /// Answer `prompt` after the history and add the turn
+    async fn turn<S: ResponseSink>(
+        &mut self,
+        prompt: Message,
+        sink: &mut S,
+    ) -> anyhow::Result<Reply> {
+        sink.output_start().await?;
+        // The oldest turns may be summarized first
+        let Some(history) = interruptible(sink, self.history(&prompt)).await else {
+            sink.output_finished(&None).await?;
+            return Ok(Reply {
+                interrupted: true,
+                ..Default::default()
+            });
+        };
+        let reply = self.inner.request(prompt.clone(), history, sink).await?;
+        self.record(prompt, &reply);
+        sink.output_finished(&self.shown_usage()).await?;
+
+        Ok(reply)
+    }
+
+    /// Make `target` the last turn of the history, `None` before the first turn
+    fn checkout(&mut self, target: Option<usize>) -> anyhow::Result<()> {
+        let old = self.tree.path(self.tree.current());
+        let new = self.tree.path(target);
+        let common = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+
+        let popped = old.len() - common;
+        if popped > self.verbatim {
+            return Err(anyhow::anyhow!(
+                "The history before this turn was compacted, its branches could not be restored"
+            ));
+        }
+        self.tree.set_current(target)?;
+
+        self.chat_log.truncate(self.chat_log.len() - 2 * popped);
+        for id in &new[common..] {
+            if let Some(turn) = self.tree.get(*id) {
+                self.chat_log.push(turn.message());
+                self.chat_log.push(Message::assistant(turn.answer.clone()));
+            }
+        }
+        self.verbatim = self.verbatim - popped + (new.len() - common);
+
+        self.transcript
+            .push(TranscriptEntry::Checkout { turn: target });
+        Ok(())
+    }
+
+    /// Go back before the last turn to answer it again, return its prompt
+    fn rewind(&mut self) -> anyhow::Result<Message> {
+        let turn = self
+            .tree
+            .current()
+            .and_then(|id| self.tree.get(id))
+            .ok_or_else(|| anyhow::anyhow!("No previous turn"))?;
+        let (parent, prompt) = (turn.parent, turn.message());
+
+        self.checkout(parent)?;
+        Ok(prompt)
+    }
+
+    /// History sent with `prompt`, cut to fit in the context window
+    async fn history(&mut self, prompt: &Message) -> Vec<Message> {
+        let fixed =
+            estimate_tokens(self.inner.preamble().unwrap_or_default()) + message_tokens(prompt);
+        let split = self
+            .context
+            .split(self.inner.model(), fixed, &self.chat_log);
+        if split == 0 {
+            return self.chat_log.clone();
+        }
+
+        if self.context.strategy() == ContextStrategy::Summarize {
+            match self.compact(split).await {
+                Ok(_) => {
+                    tracing::info!(messages = split, "Summarized the oldest turns");
+                    return self.chat_log.clone();
+                }
+                Err(e) => {
+                    tracing::warn!(error=%e, "Failed to summarize, dropping the oldest turns");
+                }
+            }
+        }
+
+        tracing::info!(
+            messages = split,
+            "Dropped the oldest turns to fit the context"
+        );
+        self.chat_log[split..].to_vec()
+    }
+
+    /// Replace the first `count` messages of the history with a summary
+    async fn compact(&mut self, count: usize) -> anyhow::Result<String> {
+        let text = plain_text(&self.chat_log[..count]);
+        let (summary, usage) = metered(self.inner.summarize(&text)).await;
+        if !usage.is_empty() {
+            self.usage.record(self.inner.model(), usage);
+        }
+        let summary = summary?;
+
+        let kept = self.chat_log.len() - count;
+        self.chat_log.splice(..count, summary_messages(&summary));
+        self.verbatim = self.verbatim.min(kept / 2);
+        self.transcript.push(TranscriptEntry::Compacted {
+            messages: count,
+            summary: summary.clone(),
+        });
+
+        Ok(summary)
+    }
+
+    /// Add a turn to the history and its usage to the totals
+    fn record(&mut self, prompt: Message, reply: &Reply) {
+        self.record_usage();
+
+        if reply.interrupted && (!self.keep_interrupted || reply.text.is_empty()) {
+            return;
+        }
+
+        self.chat_log.push(prompt.clone());
+        self.chat_log.push(Message::assistant(reply.text.clone()));
+        self.verbatim += 1;
+
+        let id = self.tree.push(Turn {
+            prompt: prompt_text(&prompt),
+            message: has_attachments(&prompt).then(|| Box::new(prompt)),
+            answer: reply.text.clone(),
+            reasoning: reply.reasoning.clone(),
+            tool_calls: reply.tool_calls.clone(),
+            usage: self.inner.usage().filter(|usage| !usage.is_empty()),
+            model: self.inner.model().map(str::to_string),
+            ..Default::default()
+        });
+        if let Some(turn) = self.tree.get(id) {
+            self.transcript.push(TranscriptEntry::Turn {
+                id,
+                turn: turn.clone(),
+            });
+        }
+    }
+
+    /// Add the usage of the last request to the totals
+    fn record_usage(&mut self) {
+        if let Some(usage) = self.inner.usage().filter(|usage| !usage.is_empty()) {
+            self.usage.record(self.inner.model(), usage);
+        }
+    }
+
+    /// Usage of the last request, if it should be shown
+    fn shown_usage(&self) -> Option<Usage> {
+        if self.inner.show_usage() {
+            self.inner
+                .usage()
+                .filter(|usage| !usage.is_empty())
+                .map(Into::into)
+        } else {
+            None
+        }
+    }
+
+    /// Execute a slash command, failures are reported to the sink
+    async fn execute<S: ResponseSink>(
+        &mut self,
+        command: Command,
+        sink: &mut S,
+    ) -> anyhow::Result<()> {
+        match command {
+            Command::Ingest(path) => {
+                let Some(knowledge) = self.inner.knowledge() else {
+                    sink.output_error(&"No knowledge base attached").await?;
+                    return Ok(());
+                };
+
+                match interruptible(sink, knowledge.ingest(&path)).await {
+                    // The files done so far stay indexed
+                    None => {}
+                    Some(Ok(count)) => {
+                        let msg = format!("Ingested {count} chunks from {}\n", path.display());
+                        sink.output_text(&msg).await?;
+                    }
+                    Some(Err(e)) => sink.output_error(&e).await?,
+                }
+            }
+            Command::Agent(None) => {
+                let agents = self.inner.agents();
+                if agents.is_empty() {
+                    sink.output_error(&"No agent profiles provided").await?;
+                } else {
+                    let msg = format!("Available agents: {}\n", agents.join(", "));
+                    sink.output_text(&msg).await?;
+                }
+            }
+            Command::Agent(Some(name)) => match self.inner.switch_agent(&name) {
+                Ok(()) => {
+                    let msg = format!("Switched to agent `{name}`\n");
+                    sink.output_text(&msg).await?;
+                }
+                Err(e) => sink.output_error(&e).await?,
+            },
+            Command::Usage => sink.output_text(&self.usage.report()).await?,
+            Command::Compact => {
+                if self.chat_log.is_empty() {
+                    sink.output_error(&"Nothing to compact").await?;
+                    return Ok(());
+                }
+
+                let before: usize = self.chat_log.iter().map(message_tokens).sum();
+                let count = self.chat_log.len();
+                match interruptible(sink, self.compact(count)).await {
+                    None => {}
+                    Some(Ok(_)) => {
+                        let after: usize = self.chat_log.iter().map(message_tokens).sum();
+                        let msg = format!(
+                            "Compacted {count} messages into a summary, about {before} -> {after} tokens\n"
+                        );
+                        sink.output_text(&msg).await?;
+                    }
+                    Some(Err(e)) => sink.output_error(&e).await?,
+                }
+            }
+            Command::Retry => match self.rewind() {
+                Ok(prompt) => {
+                    self.turn(prompt, sink).await?;
+                }
+                Err(e) => sink.output_error(&e).await?,
+            },
+            Command::Edit(input) => {
+                let prompt = match self.prompt(&input) {
+                    Ok(prompt) => prompt,
+                    Err(e) => {
+                        sink.output_error(&e).await?;
+                        return Ok(());
+                    }
+                };
+                match self.rewind() {
+                    Ok(_) => {
+                        self.turn(prompt, sink).await?;
+                    }
+                    Err(e) => sink.output_error(&e).await?,
+                }
+            }
+            Command::Branch(None) => {
+                let turns = self.tree.render();
+                if turns.is_empty() {
+                    sink.output_text(&"No turns yet\n").await?;
+                } else {
+                    let msg = format!("{turns}Go on after a turn with /branch <turn>\n");
+                    sink.output_text(&msg).await?;
+                }
+            }
+            Command::Branch(Some(id)) => match self.checkout(Some(id)) {
+                Ok(()) => {
+                    if let Some(turn) = self.tree.get(id) {
+                        let msg = format!(
+                            "Going on after turn {id}\n> {}\n{}\n",
+                            turn.prompt, turn.answer
+                        );
+                        sink.output_text(&msg).await?;
+                    }
+                }
+                Err(e) => sink.output_error(&e).await?,
+            },
+            Command::Export(path) => match self.export().write(&path) {
+                Ok(()) => {
+                    let msg = format!("Exported the session to {}\n", path.display());
+                    sink.output_text(&msg).await?;
+                }
+                Err(e) => sink.output_error(&e).await?,
+            },
+            Command::Invalid(msg) => sink.output_error(&msg).await?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use rig::completion::PromptError;
+
+    use super::*;
+    use crate::agent::{context::message_text, oneshot::CaptureSink};
+
+    /// Answer with the prompt and the size of the history
+    struct Echo;
+
+    impl Chat for Echo {
+        fn chat(
+            &self,
+            prompt: impl Into<Message> + Send,
+            chat_history: Vec<Message>,
+        ) -> impl IntoFuture<Output = Result<String, PromptError>, IntoFuture: Send> {
+            let answer = format!(
+                "{} after {}",
+                prompt_text(&prompt.into()),
+                chat_history.len()
+            );
+            async move { Ok(answer) }
+        }
+    }
+
+    fn echo_session() -> Session<ChatImpl<Echo>> {
+        SessionBuilder::new().chat(Echo).build()
+    }
+
+    /// Session after the turns `a`, `b` and `c`
+    async fn three_turns() -> Session<ChatImpl<Echo>> {
+        let mut session = echo_session();
+        for prompt in ["a", "b", "c"] {
+            session.ask(prompt, &mut CaptureSink::new()).await.unwrap();
+        }
+        session
+    }
+
+    fn texts(chat_log: &[Message]) -> Vec<String> {
+        chat_log.iter().map(message_text).collect()
+    }
+
+    #[tokio::test]
+    async fn records_the_turns() {
+        let session = three_turns().await;
+
+        assert_eq!(
+            texts(&session.chat_log),
+            ["a", "a after 0", "b", "b after 2", "c", "c after 4"]
+        );
+        assert_eq!(session.tree.current(), Some(2));
+        assert_eq!(session.verbatim, 3);
+    }
+
+    #[tokio::test]
+    async fn checkout_truncates_and_restores_the_history() {
+        let mut session = three_turns().await;
+
+        session.checkout(Some(0)).unwrap();
+        assert_eq!(texts(&session.chat_log), ["a", "a after 0"]);
+        assert_eq!(session.verbatim, 1);
+
+        session.ask("d", &mut CaptureSink::new()).await.unwrap();
+        assert_eq!(
+            texts(&session.chat_log),
+            ["a", "a after 0", "d", "d after 2"]
+        );
+        assert_eq!(session.tree.path(session.tree.current()), [0, 3]);
+
+        session.checkout(Some(2)).unwrap();
+        assert_eq!(
+            texts(&session.chat_log),
+            ["a", "a after 0", "b", "b after 2", "c", "c after 4"]
+        );
+        assert_eq!(session.verbatim, 3);
+
+        session.checkout(None).unwrap();
+        assert!(session.chat_log.is_empty());
+    }
+
+    #[tokio::test]
+    async fn checkout_keeps_compacted_turns() {
+        let mut session = three_turns().await;
+        // The first turn was summarized
+        session.verbatim = 2;
+
+        assert!(session.checkout(Some(1)).is_ok());
+        assert!(session.checkout(None).is_err());
+        assert_eq!(session.tree.current(), Some(1));
+        assert_eq!(session.chat_log.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn rewind_goes_back_before_the_last_turn() {
+        let mut session = three_turns().await;
+
+        let prompt = session.rewind().unwrap();
+
+        assert_eq!(prompt_text(&prompt), "c");
+        assert_eq!(session.tree.current(), Some(1));
+        assert_eq!(session.chat_log.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn export_round_trip() {
+        let mut session = three_turns().await;
+        session.checkout(Some(0)).unwrap();
+        session.ask("d", &mut CaptureSink::new()).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("whisper-export-{}.json", std::process::id()));
+        session.export().write(&path).unwrap();
+        let export = SessionExport::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let resumed = echo_session().resume(&export.unwrap()).unwrap();
+
+        assert_eq!(texts(&resumed.chat_log), texts(&session.chat_log));
+        assert_eq!(resumed.tree.current(), Some(3));
+        assert_eq!(resumed.tree.turns().len(), 4);
+        assert_eq!(resumed.tree.path(Some(2)), [0, 1, 2]);
+        assert_eq!(resumed.verbatim, 2);
+    }
 }
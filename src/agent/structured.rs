@@ -0,0 +1,371 @@
+use jsonschema::Validator;
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Message, ToolDefinition},
+    message::AssistantContent,
+    providers::ollama,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::agent::usage::{TokenUsage, report};
+
+/// Tool the model must call in [`OutputMode::Tool`]
+const RESPOND_TOOL: &str = "respond";
+
+/// How structured output is asked to a model, set by `structured_output` in config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StructuredOutput {
+    /// The structured output of the provider if it has one, else a forced tool
+    #[default]
+    Native,
+    /// Always a forced tool, e.g. for servers mimicking a provider
+    Tool,
+}
+
+/// How a model is asked for an answer matching a JSON schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputMode {
+    /// OpenAI `response_format` with the schema
+    ResponseFormat,
+    /// Ollama `format` with the schema, sent to the chat API of the server
+    OllamaFormat { base_url: String, model: String },
+    /// A tool whose parameters are the schema, which the model must call
+    Tool,
+    /// A tool the model is told to call, for Ollama which has no `tool_choice`
+    /// and would get it as a model option
+    OllamaTool,
+}
+
+impl OutputMode {
+    pub fn new(provider: &str, base_url: &str, model: &str, setting: StructuredOutput) -> Self {
+        match (setting, provider) {
+            (StructuredOutput::Native, "openai") => Self::ResponseFormat,
+            (StructuredOutput::Native, "ollama") => Self::OllamaFormat {
+                base_url: base_url.trim_end_matches('/').to_string(),
+                model: model.to_string(),
+            },
+            (StructuredOutput::Tool, "ollama") => Self::OllamaTool,
+            _ => Self::Tool,
+        }
+    }
+}
+
+/// Ask the model of `agent` for an answer to `prompt` matching `schema`,
+/// return the answer as is. Tools and dynamic context of the agent are not used.
+pub async fn request<M: CompletionModel>(
+    agent: &Agent<M>,
+    mode: &OutputMode,
+    prompt: Message,
+    history: Vec<Message>,
+    schema: &Value,
+) -> anyhow::Result<String> {
+    if let OutputMode::OllamaFormat { base_url, model } = mode {
+        return request_ollama(agent, base_url, model, prompt, history, schema).await;
+    }
+
+    let mut builder = agent
+        .model
+        .completion_request(prompt)
+        .messages(history)
+        .temperature_opt(agent.temperature);
+    let mut preamble = agent.preamble.clone();
+    if *mode == OutputMode::OllamaTool {
+        let call = format!("Give your answer by calling the `{RESPOND_TOOL}` tool.");
+        preamble = Some(match preamble {
+            Some(preamble) => format!("{}\n\n{call}", preamble.trim_end()),
+            None => call,
+        });
+    }
+    if let Some(preamble) = preamble {
+        builder = builder.preamble(preamble);
+    }
+
+    let respond = ToolDefinition {
+        name: RESPOND_TOOL.to_string(),
+        description: "Give the answer, the arguments are the answer".to_string(),
+        parameters: schema.clone(),
+    };
+    builder = match mode {
+        OutputMode::ResponseFormat => builder.additional_params(json!({
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": { "name": "answer", "schema": schema, "strict": true },
+            }
+        })),
+        OutputMode::OllamaTool => builder.tool(respond),
+        _ => builder.tool(respond).additional_params(json!({
+            "tool_choice": { "type": "function", "function": { "name": RESPOND_TOOL } }
+        })),
+    };
+
+    let response = builder.send().await?;
+
+    let mut text = String::new();
+    for content in response.choice.iter() {
+        match content {
+            AssistantContent::ToolCall(call) if call.function.name == RESPOND_TOOL => {
+                return Ok(call.function.arguments.to_string());
+            }
+            AssistantContent::Text(t) => text.push_str(&t.text),
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// The chat API of Ollama, rig sends extra parameters as model options
+/// while `format` must be at the top level
+async fn request_ollama<M: CompletionModel>(
+    agent: &Agent<M>,
+    base_url: &str,
+    model: &str,
+    prompt: Message,
+    history: Vec<Message>,
+    schema: &Value,
+) -> anyhow::Result<String> {
+    let mut messages: Vec<ollama::Message> = Vec::new();
+    if let Some(preamble) = &agent.preamble {
+        messages.push(ollama::Message::system(preamble));
+    }
+    for message in history.into_iter().chain([prompt]) {
+        messages.extend(Vec::<ollama::Message>::try_from(message)?);
+    }
+
+    let body = json!({
+        "model": model,
+        "messages": messages,
+        "format": schema,
+        "stream": false,
+        "options": { "temperature": agent.temperature },
+    });
+    let response = reqwest::Client::new()
+        .post(format!("{base_url}/api/chat"))
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&body)?)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let bytes = response.bytes().await?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!(
+            "Ollama returned {status}: {}",
+            String::from_utf8_lossy(&bytes)
+        ));
+    }
+
+    let response: ollama::CompletionResponse = serde_json::from_slice(&bytes)?;
+    report(TokenUsage {
+        input: response.prompt_eval_count.unwrap_or(0),
+        output: response.eval_count.unwrap_or(0),
+        cached: 0,
+    });
+
+    match response.message {
+        ollama::Message::Assistant { content, .. } => Ok(content),
+        _ => Err(anyhow::anyhow!("Ollama did not answer as the assistant")),
+    }
+}
+
+/// Compile `schema`, every keyword of JSON Schema is checked, `format` included
+pub fn validator(schema: &Value) -> anyhow::Result<Validator> {
+    jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|e| anyhow::anyhow!("Invalid JSON schema: {e}"))
+}
+
+/// Parse an answer and validate it, return the errors if any
+pub fn check_answer(validator: &Validator, answer: &str) -> Result<Value, Vec<String>> {
+    let value: Value = serde_json::from_str(strip_fence(answer))
+        .map_err(|e| vec![format!("The answer is not valid JSON: {e}")])?;
+
+    let errors = validate(validator, &value);
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Sent back to the model when its answer does not match the schema
+pub fn retry_prompt(errors: &[String]) -> String {
+    format!(
+        "Your answer does not match the JSON schema:\n- {}\nAnswer again with the corrected JSON only.",
+        errors.join("\n- ")
+    )
+}
+
+/// The JSON in a markdown code block, as some models answer
+fn strip_fence(answer: &str) -> &str {
+    let answer = answer.trim();
+
+    answer
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|code| {
+            // Drop the language after the opening fence
+            code.split_once('\n').map_or(code, |(_, code)| code).trim()
+        })
+        .unwrap_or(answer)
+}
+
+/// Check `value`, return the errors found
+pub fn validate(validator: &Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|e| format!("{}: {e}", at(&e.instance_path.to_string())))
+        .collect()
+}
+
+/// Where an error is, as a JSON pointer
+fn at(path: &str) -> String {
+    if path.is_empty() {
+        "At the root".to_string()
+    } else {
+        format!("At {path}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person() -> Validator {
+        validator(&json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "email": { "type": "string", "format": "email" },
+                "tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_a_matching_answer() {
+        let value = check_answer(&person(), r#"{"name": "Ann", "age": 3}"#).unwrap();
+
+        assert_eq!(value, json!({ "name": "Ann", "age": 3 }));
+    }
+
+    #[test]
+    fn strips_a_code_fence() {
+        let answer = "```json\n{\"name\": \"Ann\", \"age\": 3}\n```";
+
+        assert!(check_answer(&person(), answer).is_ok());
+        assert_eq!(strip_fence("```\n[1]\n```"), "[1]");
+        assert_eq!(strip_fence(" [1] "), "[1]");
+    }
+
+    #[test]
+    fn reports_invalid_json() {
+        let errors = check_answer(&person(), "Ann is 3").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("The answer is not valid JSON"));
+    }
+
+    #[test]
+    fn reports_every_error_with_its_location() {
+        let errors = validate(
+            &person(),
+            &json!({ "age": -1, "email": "nope", "tags": ["a", "a"], "extra": true }),
+        );
+
+        assert_eq!(errors.len(), 5, "{errors:?}");
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.starts_with("At the root: ") && e.contains("name"))
+        );
+        assert!(errors.iter().any(|e| e.starts_with("At /age: ")));
+        assert!(errors.iter().any(|e| e.starts_with("At /email: ")));
+        assert!(errors.iter().any(|e| e.starts_with("At /tags: ")));
+        assert!(errors.iter().any(|e| e.contains("extra")));
+    }
+
+    #[test]
+    fn checks_pattern_and_multiple_of() {
+        let checker = validator(&json!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "pattern": "^[A-Z]{3}$" },
+                "step": { "type": "number", "multipleOf": 5 },
+            },
+        }))
+        .unwrap();
+
+        assert!(validate(&checker, &json!({ "code": "ABC", "step": 10 })).is_empty());
+        assert_eq!(
+            validate(&checker, &json!({ "code": "abc", "step": 7 })).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn follows_recursive_references() {
+        let checker = validator(&json!({
+            "type": "object",
+            "properties": { "child": { "$ref": "#" }, "name": { "type": "string" } },
+        }))
+        .unwrap();
+
+        assert!(validate(&checker, &json!({ "child": { "child": { "name": "x" } } })).is_empty());
+        let errors = validate(&checker, &json!({ "child": { "child": { "name": 1 } } }));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("At /child/child/name: "));
+
+        // Would recurse forever if references were resolved eagerly
+        let itself = validator(&json!({ "$ref": "#" })).unwrap();
+        assert!(validate(&itself, &json!(1)).is_empty());
+    }
+
+    #[test]
+    fn rejects_an_invalid_schema() {
+        assert!(validator(&json!({ "type": "strin" })).is_err());
+    }
+
+    #[test]
+    fn retry_prompt_lists_the_errors() {
+        let prompt = retry_prompt(&["one".to_string(), "two".to_string()]);
+
+        assert!(prompt.contains("\n- one\n- two\n"));
+    }
+
+    #[test]
+    fn chooses_the_output_mode() {
+        let native = StructuredOutput::Native;
+
+        assert_eq!(
+            OutputMode::new("openai", "", "gpt", native),
+            OutputMode::ResponseFormat
+        );
+        assert_eq!(
+            OutputMode::new("ollama", "http://host/", "qwen", native),
+            OutputMode::OllamaFormat {
+                base_url: "http://host".to_string(),
+                model: "qwen".to_string(),
+            }
+        );
+        assert_eq!(
+            OutputMode::new("anthropic", "", "claude", native),
+            OutputMode::Tool
+        );
+        assert_eq!(
+            OutputMode::new("openai", "", "gpt", StructuredOutput::Tool),
+            OutputMode::Tool
+        );
+        assert_eq!(
+            OutputMode::new("ollama", "", "qwen", StructuredOutput::Tool),
+            OutputMode::OllamaTool
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+/// Variables of a preamble, written `{{name}}` in the text.
+///
+/// Unknown variables are left as they are.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars(HashMap<String, String>);
+
+impl TemplateVars {
+    /// `date`, `cwd`, `os`, `user` and `git_branch` of the running process,
+    /// empty when unknown
+    pub fn from_env() -> Self {
+        let cwd = env::current_dir().ok();
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default();
+
+        Self::default()
+            .set("date", Local::now().format("%Y-%m-%d").to_string())
+            .set(
+                "cwd",
+                cwd.as_deref()
+                    .map(|cwd| cwd.display().to_string())
+                    .unwrap_or_default(),
+            )
+            .set("os", env::consts::OS)
+            .set("user", user)
+            .set(
+                "git_branch",
+                cwd.as_deref().and_then(git_branch).unwrap_or_default(),
+            )
+    }
+
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.0.insert(name.into(), value.into());
+        self
+    }
+
+    /// Add the variables of the config, they override the others
+    pub fn extend(mut self, vars: &HashMap<String, String>) -> Self {
+        self.0.extend(
+            vars.iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        self
+    }
+
+    /// Replace the variables of `template`
+    pub fn render(&self, template: &str) -> String {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+
+            let Some(end) = after.find("}}") else {
+                rest = &rest[start..];
+                break;
+            };
+            let name = after[..end].trim();
+            match self.0.get(name) {
+                Some(value) => output.push_str(value),
+                None => {
+                    tracing::warn!(name, "Unknown variable in the preamble");
+                    output.push_str(&rest[start..start + 2 + end + 2]);
+                }
+            }
+            rest = &after[end + 2..];
+        }
+        output.push_str(rest);
+
+        output
+    }
+}
+
+/// Branch checked out in the repository containing `dir`, or the short
+/// commit id if detached
+fn git_branch(dir: &Path) -> Option<String> {
+    let git = dir
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|git| git.exists())?;
+    let head = fs::read_to_string(git_dir(&git)?.join("HEAD")).ok()?;
+    let head = head.trim();
+
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        ),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+/// `.git` is a file pointing to the real directory in worktrees and submodules
+fn git_dir(git: &Path) -> Option<PathBuf> {
+    if git.is_dir() {
+        return Some(git.to_path_buf());
+    }
+
+    let content = fs::read_to_string(git).ok()?;
+    let path = PathBuf::from(content.trim().strip_prefix("gitdir: ")?);
+    Some(match git.parent() {
+        Some(parent) if path.is_relative() => parent.join(path),
+        _ => path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        TemplateVars::default()
+            .set("user", "ann")
+            .set("tools", "- time")
+    }
+
+    #[test]
+    fn replaces_the_variables() {
+        let text = vars().render("Hello {{user}}, tools:\n{{ tools }}\nBye {{user}}");
+
+        assert_eq!(text, "Hello ann, tools:\n- time\nBye ann");
+    }
+
+    #[test]
+    fn keeps_unknown_variables() {
+        assert_eq!(vars().render("{{user}} {{unknown}}!"), "ann {{unknown}}!");
+    }
+
+    #[test]
+    fn keeps_unclosed_braces() {
+        assert_eq!(vars().render("{{user}} {{user"), "ann {{user");
+        assert_eq!(vars().render("a }} b {"), "a }} b {");
+    }
+
+    #[test]
+    fn config_variables_override_the_others() {
+        let config = HashMap::from([("user".to_string(), "bob".to_string())]);
+
+        assert_eq!(vars().extend(&config).render("{{user}}"), "bob");
+    }
+
+    #[test]
+    fn environment_has_the_builtin_variables() {
+        let vars = TemplateVars::from_env();
+
+        assert_eq!(vars.render("{{os}}"), env::consts::OS);
+        assert_eq!(vars.render("{{date}}").len(), "2025-01-01".len());
+    }
+}
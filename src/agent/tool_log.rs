@@ -0,0 +1,72 @@
+use std::{cell::RefCell, future::Future, pin::Pin};
+
+use rig::{
+    completion::ToolDefinition,
+    tool::{ToolDyn, ToolError},
+};
+
+use crate::agent::oneshot::ToolCallRecord;
+
+tokio::task_local! {
+    /// Tools called by the current request
+    static TOOL_CALLS: RefCell<Vec<ToolCallRecord>>;
+}
+
+/// Run `future`, collecting the calls of the [`RecordedTool`]s it makes
+pub async fn recorded<F: Future>(future: F) -> (F::Output, Vec<ToolCallRecord>) {
+    TOOL_CALLS
+        .scope(RefCell::new(Vec::new()), async move {
+            let output = future.await;
+            (output, TOOL_CALLS.with(RefCell::take))
+        })
+        .await
+}
+
+fn record(name: String, arguments: &str, result: String) {
+    let arguments = serde_json::from_str(arguments)
+        .unwrap_or_else(|_| serde_json::Value::String(arguments.to_string()));
+
+    let _ = TOOL_CALLS.try_with(|calls| {
+        calls.borrow_mut().push(ToolCallRecord {
+            name,
+            arguments,
+            result: Some(result),
+        })
+    });
+}
+
+/// Tool reporting its calls and results to [`recorded`].
+///
+/// The streamed answer of a multi turn request does not hold the tool calls,
+/// the agent runs them before going on.
+pub struct RecordedTool<T>(pub T);
+
+impl<T: ToolDyn> ToolDyn for RecordedTool<T> {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn definition(
+        &self,
+        prompt: String,
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + Sync + '_>> {
+        self.0.definition(prompt)
+    }
+
+    fn call(
+        &self,
+        args: String,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + '_>> {
+        Box::pin(async move {
+            let result = self.0.call(args.clone()).await;
+
+            let output = match &result {
+                Ok(output) => output.clone(),
+                Err(e) => e.to_string(),
+            };
+            record(self.0.name(), &args, output);
+
+            result
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::branch::Turn;
+
+/// Transcripts of the interactive sessions
+pub const DEFAULT_SESSION_DIR: &str = ".whisper/sessions";
+
+/// One line of a transcript file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    Turn {
+        id: usize,
+        #[serde(flatten)]
+        turn: Turn,
+    },
+    /// The first `messages` of the history were replaced by `summary`
+    Compacted { messages: usize, summary: String },
+    /// The conversation goes on after `turn`, on another branch
+    Checkout { turn: Option<usize> },
+}
+
+/// Everything said in a session, also the turns compacted out of the history
+/// and the other branches.
+///
+/// Entries are appended to a JSON Lines file if a directory is set.
+#[derive(Debug, Default)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+    path: Option<PathBuf>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write to a new file in `dir`, named after the current time
+    pub fn dir(mut self, dir: impl AsRef<Path>) -> Self {
+        let name = Local::now().format("%Y%m%d-%H%M%S%.3f");
+        self.path = Some(dir.as_ref().join(format!("{name}.jsonl")));
+        self
+    }
+
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    pub fn push(&mut self, entry: TranscriptEntry) {
+        if let Some(path) = &self.path
+            && let Err(e) = append_entry(path, &entry)
+        {
+            tracing::warn!(error=%e, path=%path.display(), "Failed to save the transcript");
+        }
+
+        self.entries.push(entry);
+    }
+}
+
+fn append_entry(path: &Path, entry: &TranscriptEntry) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
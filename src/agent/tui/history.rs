@@ -0,0 +1,82 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+/// One block of the conversation
+#[derive(Debug)]
+pub enum Entry {
+    User(String),
+    Assistant {
+        text: String,
+        reasoning: String,
+    },
+    /// Output of slash commands
+    Info(String),
+    Error(String),
+}
+
+/// A tool call shown in the sidebar
+#[derive(Debug)]
+pub struct ToolCallEntry {
+    pub name: String,
+    pub arguments: String,
+}
+
+impl Entry {
+    /// Render the entry, the reasoning is folded into one line if not `show_reasoning`
+    pub fn lines(&self, show_reasoning: bool) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+
+        match self {
+            Entry::User(text) => {
+                lines.push(header("You", Color::Green));
+                lines.extend(text.lines().map(|l| Line::raw(l.to_string())));
+            }
+            Entry::Assistant { text, reasoning } => {
+                lines.push(header("Assistant", Color::Blue));
+
+                let reasoning = reasoning.trim();
+                if !reasoning.is_empty() {
+                    let style = Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::ITALIC);
+                    if show_reasoning {
+                        lines.push(Line::styled("▾ Reasoning", style));
+                        lines.extend(
+                            reasoning
+                                .lines()
+                                .map(|l| Line::styled(format!("  {l}"), style)),
+                        );
+                    } else {
+                        let count = reasoning.lines().count();
+                        lines.push(Line::styled(
+                            format!("▸ Reasoning ({count} lines, Ctrl-R to expand)"),
+                            style,
+                        ));
+                    }
+                }
+
+                lines.extend(text.trim_start().lines().map(|l| Line::raw(l.to_string())));
+            }
+            Entry::Info(text) => {
+                let style = Style::default().fg(Color::Cyan);
+                lines.extend(text.lines().map(|l| Line::styled(l.to_string(), style)));
+            }
+            Entry::Error(text) => {
+                let style = Style::default().fg(Color::Red);
+                lines.extend(text.lines().map(|l| Line::styled(format!("✗ {l}"), style)));
+            }
+        }
+
+        lines.push(Line::default());
+        lines
+    }
+}
+
+fn header(name: &'static str, color: Color) -> Line<'static> {
+    Line::from(Span::styled(
+        name,
+        Style::default().fg(color).add_modifier(Modifier::BOLD),
+    ))
+}
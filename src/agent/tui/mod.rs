@@ -0,0 +1,376 @@
+pub mod history;
+mod view;
+
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::{
+    DefaultTerminal,
+    style::{Color, Style},
+    widgets::{Block, Borders},
+};
+use rig::completion::Usage;
+use tui_textarea::TextArea;
+
+use crate::{
+    agent::session::{InputSource, ResponseSink, SinkError},
+    mcp::manager::McpManager,
+};
+use history::{Entry, ToolCallEntry};
+
+/// Lines scrolled by PageUp and PageDown
+const PAGE_LINES: u16 = 10;
+/// Tool arguments longer than this are cut in the sidebar
+const MAX_ARGUMENTS_CHARS: usize = 120;
+
+/// Everything shown on screen
+pub struct TuiState<'a> {
+    history: Vec<Entry>,
+    tool_calls: Vec<ToolCallEntry>,
+    input: TextArea<'static>,
+    /// Lines scrolled up from the bottom of the history
+    scroll_back: u16,
+    show_reasoning: bool,
+    show_tools: bool,
+    /// An answer is streaming
+    busy: bool,
+    is_reasoning: bool,
+    /// Ctrl-C was pressed on an empty message, another one quits
+    pending_exit: bool,
+    model: String,
+    last_usage: Option<Usage>,
+    total_usage: Usage,
+    mcp: &'a McpManager,
+}
+
+/// What a key press asks the frontend to do
+enum Action {
+    Submit(String),
+    /// Stop the answer
+    Interrupt,
+    Quit,
+}
+
+/// Full-screen frontend, the terminal is restored when it is dropped
+pub struct TuiFrontend<'a> {
+    terminal: DefaultTerminal,
+    events: EventStream,
+    state: TuiState<'a>,
+}
+
+impl<'a> TuiFrontend<'a> {
+    /// Take over the terminal, `model` is shown in the status bar
+    pub fn new(model: impl Into<String>, mcp: &'a McpManager) -> std::io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+
+        Ok(Self {
+            terminal,
+            events: EventStream::new(),
+            state: TuiState {
+                history: Vec::new(),
+                tool_calls: Vec::new(),
+                input: new_input(),
+                scroll_back: 0,
+                show_reasoning: false,
+                show_tools: true,
+                busy: false,
+                is_reasoning: false,
+                pending_exit: false,
+                model: model.into(),
+                last_usage: None,
+                total_usage: Usage::default(),
+                mcp,
+            },
+        })
+    }
+
+    fn draw(&mut self) -> Result<(), SinkError> {
+        self.terminal
+            .draw(|frame| view::draw(frame, &mut self.state))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for TuiFrontend<'_> {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn new_input() -> TextArea<'static> {
+    let mut input = TextArea::default();
+    input.set_block(Block::default().borders(Borders::ALL).title(" Message "));
+    input.set_cursor_line_style(Style::default());
+    input.set_placeholder_text("Ask anything, or /agent, /ingest <path>");
+    input.set_placeholder_style(Style::default().fg(Color::DarkGray));
+    input
+}
+
+impl TuiState<'_> {
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        if key.code == KeyCode::Char('c') && ctrl {
+            return self.handle_ctrl_c();
+        }
+        self.pending_exit = false;
+
+        match key.code {
+            KeyCode::Enter if alt => self.input.insert_newline(),
+            KeyCode::Char('j') if ctrl => self.input.insert_newline(),
+            KeyCode::Enter => {
+                let text = self.input.lines().join("\n");
+                if self.busy || text.trim().is_empty() {
+                    return None;
+                }
+                self.input = new_input();
+                self.scroll_back = 0;
+                return Some(Action::Submit(text.trim().to_string()));
+            }
+            KeyCode::Char('d') if ctrl && self.input.is_empty() => return Some(Action::Quit),
+            KeyCode::Char('r') if ctrl => self.show_reasoning = !self.show_reasoning,
+            KeyCode::Char('t') if ctrl => self.show_tools = !self.show_tools,
+            KeyCode::PageUp => self.scroll_back = self.scroll_back.saturating_add(PAGE_LINES),
+            KeyCode::PageDown => self.scroll_back = self.scroll_back.saturating_sub(PAGE_LINES),
+            KeyCode::Esc => self.input = new_input(),
+            _ => {
+                self.input.input(key);
+            }
+        }
+
+        None
+    }
+
+    /// Stop the answer, clear the message, or quit when pressed twice
+    fn handle_ctrl_c(&mut self) -> Option<Action> {
+        if self.busy {
+            return Some(Action::Interrupt);
+        }
+        if !self.input.is_empty() {
+            self.input = new_input();
+            return None;
+        }
+        if self.pending_exit {
+            return Some(Action::Quit);
+        }
+
+        self.pending_exit = true;
+        self.history.push(Entry::Info(
+            "Press Ctrl-C again or Ctrl-D to quit".to_string(),
+        ));
+        None
+    }
+
+    /// Append streamed text to the current answer, or show it as command output
+    fn append(&mut self, content: String) {
+        match self.history.last_mut() {
+            Some(Entry::Assistant { text, reasoning }) if self.busy => {
+                if self.is_reasoning {
+                    reasoning.push_str(&content);
+                } else {
+                    text.push_str(&content);
+                }
+            }
+            // The answer goes on after an error
+            _ if self.busy => {
+                let (text, reasoning) = if self.is_reasoning {
+                    (String::new(), content)
+                } else {
+                    (content, String::new())
+                };
+                self.history.push(Entry::Assistant { text, reasoning });
+            }
+            _ => self
+                .history
+                .push(Entry::Info(content.trim_end().to_string())),
+        }
+    }
+}
+
+impl InputSource for TuiFrontend<'_> {
+    async fn read_input(&mut self) -> Result<Option<String>, SinkError> {
+        loop {
+            self.draw()?;
+
+            let Some(event) = self.events.next().await else {
+                return Ok(None);
+            };
+            let Event::Key(key) = event? else {
+                // Resizing only needs a redraw
+                continue;
+            };
+
+            match self.state.handle_key(key) {
+                Some(Action::Submit(text)) => {
+                    self.state.history.push(Entry::User(text.clone()));
+                    self.draw()?;
+                    return Ok(Some(text));
+                }
+                Some(Action::Quit) => return Ok(None),
+                Some(Action::Interrupt) | None => {}
+            }
+        }
+    }
+}
+
+impl ResponseSink for TuiFrontend<'_> {
+    async fn chat_start(&mut self) -> Result<(), SinkError> {
+        self.state.history.push(Entry::Info(
+            "Welcome to whisper, answers stream here. Ctrl-D on an empty message quits."
+                .to_string(),
+        ));
+
+        self.draw()
+    }
+
+    async fn user_start(&mut self) -> Result<(), SinkError> {
+        self.state.busy = false;
+
+        self.draw()
+    }
+
+    async fn output_start(&mut self) -> Result<(), SinkError> {
+        self.state.busy = true;
+        self.state.is_reasoning = false;
+        self.state.history.push(Entry::Assistant {
+            text: String::new(),
+            reasoning: String::new(),
+        });
+
+        self.draw()
+    }
+
+    async fn output_text(
+        &mut self,
+        content: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.state.append(content.to_string());
+
+        self.draw()
+    }
+
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        let mut arguments = arguments.to_string();
+        if let Some((index, _)) = arguments.char_indices().nth(MAX_ARGUMENTS_CHARS) {
+            arguments.truncate(index);
+            arguments.push('…');
+        }
+
+        self.state.tool_calls.push(ToolCallEntry {
+            name: name.to_string(),
+            arguments,
+        });
+
+        self.draw()
+    }
+
+    async fn output_reason_start(&mut self) -> Result<(), SinkError> {
+        self.state.is_reasoning = true;
+
+        Ok(())
+    }
+
+    async fn output_reason_end(&mut self) -> Result<(), SinkError> {
+        self.state.is_reasoning = false;
+
+        Ok(())
+    }
+
+    async fn output_finished(&mut self, usage: &Option<Usage>) -> Result<(), SinkError> {
+        self.state.busy = false;
+        if let Some(usage) = usage {
+            self.state.last_usage = Some(*usage);
+            self.state.total_usage += *usage;
+        }
+
+        self.draw()
+    }
+
+    async fn chat_finished(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn approve_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<bool, SinkError> {
+        self.state.history.push(Entry::Info(format!(
+            "🔧 {name} {arguments}\nAllow this call? [y/n]"
+        )));
+        self.draw()?;
+
+        let mut approved = false;
+        while let Some(event) = self.events.next().await {
+            let Event::Key(key) = event? else {
+                self.draw()?;
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('y' | 'Y') => approved = true,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {}
+                KeyCode::Char('n' | 'N') | KeyCode::Esc => {}
+                _ => continue,
+            }
+            break;
+        }
+
+        let answer = if approved { "Allowed" } else { "Refused" };
+        self.state.history.push(Entry::Info(answer.to_string()));
+        self.draw()?;
+
+        Ok(approved)
+    }
+
+    async fn interrupted(&mut self) {
+        // Keys are read here while answering, the submitted ones wait for the answer
+        while let Some(event) = self.events.next().await {
+            let Ok(Event::Key(key)) = event else {
+                let _ = self.draw();
+                continue;
+            };
+
+            // Stops the work waited for, even if it is not an answer
+            let ctrl_c = key.code == KeyCode::Char('c')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+                && key.kind == KeyEventKind::Press;
+            let action = if ctrl_c {
+                Some(Action::Interrupt)
+            } else {
+                self.state.handle_key(key)
+            };
+            if let Some(Action::Interrupt) = action {
+                self.state
+                    .history
+                    .push(Entry::Info("⏹ Interrupted".to_string()));
+                let _ = self.draw();
+                return;
+            }
+            let _ = self.draw();
+        }
+
+        std::future::pending().await
+    }
+
+    async fn output_error(
+        &mut self,
+        e: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.state.history.push(Entry::Error(e.to_string()));
+
+        self.draw()
+    }
+}
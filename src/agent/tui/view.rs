@@ -0,0 +1,136 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+};
+
+use super::TuiState;
+
+/// Height of the input box, borders included
+const MAX_INPUT_HEIGHT: u16 = 10;
+
+pub fn draw(frame: &mut Frame, state: &mut TuiState<'_>) {
+    let input_lines = u16::try_from(state.input.lines().len()).unwrap_or(u16::MAX);
+    let input_height = input_lines.saturating_add(2).clamp(3, MAX_INPUT_HEIGHT);
+    let [main, input, status] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(input_height),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    if state.show_tools {
+        let [history, tools] =
+            Layout::horizontal([Constraint::Percentage(72), Constraint::Percentage(28)])
+                .areas(main);
+        draw_history(frame, state, history);
+        draw_tools(frame, state, tools);
+    } else {
+        draw_history(frame, state, main);
+    }
+
+    frame.render_widget(&state.input, input);
+    draw_status(frame, state, status);
+}
+
+fn draw_history(frame: &mut Frame, state: &mut TuiState<'_>, area: Rect) {
+    let lines: Vec<Line> = state
+        .history
+        .iter()
+        .flat_map(|entry| entry.lines(state.show_reasoning))
+        .collect();
+
+    let title = if state.busy {
+        " Conversation (answering…) "
+    } else {
+        " Conversation "
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner_width = area.width.saturating_sub(2);
+    let inner_height = area.height.saturating_sub(2);
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    let total = u16::try_from(paragraph.line_count(inner_width)).unwrap_or(u16::MAX);
+
+    // `scroll_back` counts lines from the bottom, so new output stays visible
+    let max_scroll = total.saturating_sub(inner_height);
+    state.scroll_back = state.scroll_back.min(max_scroll);
+    let offset = max_scroll - state.scroll_back;
+
+    frame.render_widget(paragraph.block(block).scroll((offset, 0)), area);
+}
+
+fn draw_tools(frame: &mut Frame, state: &TuiState<'_>, area: Rect) {
+    let name_style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let arguments_style = Style::default().fg(Color::DarkGray);
+
+    // Latest calls first
+    let items: Vec<ListItem> = state
+        .tool_calls
+        .iter()
+        .rev()
+        .map(|call| {
+            ListItem::new(vec![
+                Line::styled(call.name.clone(), name_style),
+                Line::styled(call.arguments.clone(), arguments_style),
+            ])
+        })
+        .collect();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Tool calls ({}) ", state.tool_calls.len()));
+    frame.render_widget(List::new(items).block(block), area);
+}
+
+fn draw_status(frame: &mut Frame, state: &TuiState<'_>, area: Rect) {
+    let separator = Span::styled(" │ ", Style::default().fg(Color::DarkGray));
+    let mut spans = vec![
+        Span::styled(
+            format!(" {} ", state.model),
+            Style::default().fg(Color::Black).bg(Color::Cyan),
+        ),
+        separator.clone(),
+    ];
+
+    let usage = match &state.last_usage {
+        Some(last) => format!(
+            "tokens in {} out {} (total in {} out {})",
+            last.input_tokens,
+            last.output_tokens,
+            state.total_usage.input_tokens,
+            state.total_usage.output_tokens
+        ),
+        None => "tokens -".to_string(),
+    };
+    spans.push(Span::raw(usage));
+
+    let health = state.mcp.health();
+    if !health.is_empty() {
+        spans.push(separator.clone());
+        spans.push(Span::raw("MCP "));
+        for server in health {
+            let color = if server.connected {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            spans.push(Span::styled("● ", Style::default().fg(color)));
+            spans.push(Span::raw(format!("{} ", server.name)));
+        }
+    }
+
+    let keys = if state.busy {
+        "Ctrl-C stop · Ctrl-R reasoning · Ctrl-T tools · PgUp/PgDn"
+    } else {
+        "Enter send · Alt-Enter newline · Ctrl-R reasoning · Ctrl-T tools · PgUp/PgDn · Ctrl-D quit"
+    };
+    spans.push(separator);
+    spans.push(Span::styled(keys, Style::default().fg(Color::DarkGray)));
+
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
@@ -0,0 +1,381 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    future::Future,
+    io::{BufRead, BufReader, Write},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::Local;
+use futures::{StreamExt, future::BoxFuture};
+use rig::{
+    client::completion::CompletionModelHandle,
+    completion::{
+        CompletionError, CompletionModel, CompletionModelDyn, CompletionRequest,
+        CompletionRequestBuilder, CompletionResponse, GetTokenUsage, Message, Usage,
+    },
+    message::Reasoning,
+    streaming::{RawStreamingChoice, StreamedAssistantContent, StreamingCompletionResponse},
+};
+use serde::{Deserialize, Serialize};
+
+/// Usage log written next to the input history
+pub const DEFAULT_USAGE_DIR: &str = ".whisper/usage";
+
+/// Tokens used by one or several requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input: u64,
+    pub output: u64,
+    /// Input tokens read from the prompt cache, counted in `input` too.
+    ///
+    /// Zero when the provider does not report them.
+    #[serde(default)]
+    pub cached: u64,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            input: usage.input_tokens,
+            output: usage.output_tokens,
+            cached: 0,
+        }
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.input += rhs.input;
+        self.output += rhs.output;
+        self.cached += rhs.cached;
+    }
+}
+
+impl From<TokenUsage> for Usage {
+    fn from(usage: TokenUsage) -> Self {
+        let mut converted = Usage::new();
+        converted.input_tokens = usage.input;
+        converted.output_tokens = usage.output;
+        converted.total_tokens = usage.input + usage.output;
+        converted
+    }
+}
+
+impl TokenUsage {
+    pub fn is_empty(&self) -> bool {
+        self.input == 0 && self.output == 0
+    }
+}
+
+impl fmt::Display for TokenUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "in {} · out {} · cached {}",
+            self.input, self.output, self.cached
+        )
+    }
+}
+
+/// Price of a model in dollars per million tokens
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price of the cached input tokens, the input price if not set
+    pub cached: Option<f64>,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached.min(usage.input);
+        let uncached = usage.input - cached;
+
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached.unwrap_or(self.input)
+            + usage.output as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// One line of the daily usage log
+#[derive(Debug, Serialize, Deserialize)]
+struct UsageRecord {
+    /// RFC 3339 local time
+    time: String,
+    model: Option<String>,
+    #[serde(flatten)]
+    usage: TokenUsage,
+    /// `None` if the model has no price
+    cost: Option<f64>,
+}
+
+/// Usage of a session, with the cost estimated from the price table.
+///
+/// Every request is also appended to `<dir>/<date>.jsonl`.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    prices: HashMap<String, ModelPrice>,
+    log_dir: Option<PathBuf>,
+    requests: usize,
+    last: Option<TokenUsage>,
+    total: TokenUsage,
+    /// Cost of the priced requests
+    cost: f64,
+    /// Some requests used a model without price
+    unpriced: bool,
+}
+
+impl UsageTracker {
+    pub fn new(prices: HashMap<String, ModelPrice>) -> Self {
+        Self {
+            prices,
+            ..Default::default()
+        }
+    }
+
+    /// Append every request to a log file per day in `dir`
+    pub fn log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Add the usage of a request made with `model`
+    pub fn record(&mut self, model: Option<&str>, usage: TokenUsage) {
+        let cost = model
+            .and_then(|model| self.prices.get(model))
+            .map(|price| price.cost(&usage));
+
+        self.requests += 1;
+        self.last = Some(usage);
+        self.total += usage;
+        match cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced = true,
+        }
+
+        if let Some(dir) = &self.log_dir {
+            let record = UsageRecord {
+                time: Local::now().to_rfc3339(),
+                model: model.map(str::to_string),
+                usage,
+                cost,
+            };
+            if let Err(e) = append_record(dir, &record) {
+                tracing::warn!(error=%e, dir=%dir.display(), "Failed to log usage");
+            }
+        }
+    }
+
+    /// Usage of all the requests of the session
+    pub fn total(&self) -> TokenUsage {
+        self.total
+    }
+
+    /// Text shown by `/usage`
+    pub fn report(&self) -> String {
+        let mut report = format!("Usage of this session ({} requests)\n", self.requests);
+
+        if let Some(last) = &self.last {
+            report.push_str(&format!("  last:  {last}\n"));
+        }
+        report.push_str(&format!(
+            "  total: {}{}\n",
+            self.total,
+            format_cost(self.cost, self.unpriced)
+        ));
+
+        if let Some(dir) = &self.log_dir {
+            match read_today(dir) {
+                Ok((requests, usage, cost, unpriced)) => report.push_str(&format!(
+                    "  today: {usage}{} over {requests} requests\n",
+                    format_cost(cost, unpriced)
+                )),
+                Err(e) => tracing::warn!(error=%e, "Failed to read the usage log"),
+            }
+        }
+
+        report
+    }
+}
+
+fn format_cost(cost: f64, unpriced: bool) -> String {
+    match (cost > 0.0, unpriced) {
+        (true, false) => format!(" · ${cost:.4}"),
+        (true, true) => format!(" · ${cost:.4} (some models have no price)"),
+        (false, _) => String::new(),
+    }
+}
+
+fn log_file(dir: &Path) -> PathBuf {
+    dir.join(format!("{}.jsonl", Local::now().format("%Y-%m-%d")))
+}
+
+fn append_record(dir: &Path, record: &UsageRecord) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file(dir))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+
+    Ok(())
+}
+
+/// Requests, usage, cost and whether some are unpriced, from the log of today
+fn read_today(dir: &Path) -> anyhow::Result<(usize, TokenUsage, f64, bool)> {
+    let path = log_file(dir);
+    if !path.exists() {
+        return Ok((0, TokenUsage::default(), 0.0, false));
+    }
+
+    let mut requests = 0;
+    let mut usage = TokenUsage::default();
+    let mut cost = 0.0;
+    let mut unpriced = false;
+
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: UsageRecord = serde_json::from_str(&line)?;
+        requests += 1;
+        usage += record.usage;
+        match record.cost {
+            Some(c) => cost += c,
+            None => unpriced = true,
+        }
+    }
+
+    Ok((requests, usage, cost, unpriced))
+}
+
+tokio::task_local! {
+    /// Usage reported by the metered models for the current request
+    static METER: Cell<TokenUsage>;
+}
+
+/// Run `future`, adding up the usage reported by the [`MeteredModel`]s it calls
+pub async fn metered<F: Future>(future: F) -> (F::Output, TokenUsage) {
+    METER
+        .scope(Cell::new(TokenUsage::default()), async move {
+            let output = future.await;
+            (output, METER.with(Cell::get))
+        })
+        .await
+}
+
+/// Add the usage of a request made without a [`MeteredModel`] to [`metered`]
+pub fn report(usage: TokenUsage) {
+    let _ = METER.try_with(|meter| {
+        let mut total = meter.get();
+        total += usage;
+        meter.set(total);
+    });
+}
+
+/// Completion model reporting its usage to [`metered`].
+///
+/// The dynamic model handle drops the final response of a stream, and the
+/// usage with it, so the usage is taken before.
+#[derive(Clone)]
+pub struct MeteredModel<M>(pub M);
+
+impl<M, R> CompletionModelDyn for MeteredModel<M>
+where
+    M: CompletionModel<StreamingResponse = R> + 'static,
+    R: Clone + Unpin + GetTokenUsage + Serialize + Send + 'static,
+{
+    fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'_, Result<CompletionResponse<()>, CompletionError>> {
+        Box::pin(async move {
+            let response = self.0.completion(request).await?;
+            report(response.usage.into());
+
+            Ok(CompletionResponse {
+                choice: response.choice,
+                usage: response.usage,
+                raw_response: (),
+            })
+        })
+    }
+
+    fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'_, Result<StreamingCompletionResponse<()>, CompletionError>> {
+        Box::pin(async move {
+            let response = self.0.stream(request).await?;
+
+            // Reasoning chunks hold the reasoning so far, pass on what is new
+            let mut reasoning_len = 0;
+            let stream = response.map(move |content| {
+                Ok(match content? {
+                    StreamedAssistantContent::Text(text) => RawStreamingChoice::Message(text.text),
+                    StreamedAssistantContent::ToolCall(call) => RawStreamingChoice::ToolCall {
+                        id: call.id,
+                        call_id: call.call_id,
+                        name: call.function.name,
+                        arguments: call.function.arguments,
+                    },
+                    StreamedAssistantContent::Reasoning(Reasoning { id, reasoning, .. }) => {
+                        let reasoning = reasoning.concat();
+                        let new = reasoning.get(reasoning_len..).unwrap_or_default();
+                        reasoning_len = reasoning.len();
+
+                        RawStreamingChoice::Reasoning {
+                            id,
+                            reasoning: new.to_string(),
+                        }
+                    }
+                    StreamedAssistantContent::Final(response) => {
+                        if let Some(usage) = response.token_usage() {
+                            let mut usage = TokenUsage::from(usage);
+                            usage.cached = cached_tokens(&response);
+                            report(usage);
+                        }
+                        RawStreamingChoice::FinalResponse(())
+                    }
+                })
+            });
+
+            Ok(StreamingCompletionResponse::stream(Box::pin(stream)))
+        })
+    }
+
+    fn completion_request(
+        &self,
+        prompt: Message,
+    ) -> CompletionRequestBuilder<CompletionModelHandle<'_>> {
+        CompletionRequestBuilder::new(
+            CompletionModelHandle {
+                inner: Arc::new(self.clone()),
+            },
+            prompt,
+        )
+    }
+}
+
+/// Cached input tokens, in the fields used by OpenAI and DeepSeek
+fn cached_tokens(response: &impl Serialize) -> u64 {
+    let Ok(value) = serde_json::to_value(response) else {
+        return 0;
+    };
+    let usage = value.get("usage").unwrap_or(&value);
+
+    usage
+        .get("prompt_cache_hit_tokens")
+        .or_else(|| usage.pointer("/prompt_tokens_details/cached_tokens"))
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or(0)
+}
@@ -1,27 +1,30 @@
-use rig::{
-    agent::AgentBuilder, client::completion::CompletionModelHandle, embeddings::EmbeddingsBuilder,
-};
-use whisper::{agent::model_adaptor::load_models, config, mcp::manager::McpManagerBuilder};
+//! The chat under its historical name, for the scripts starting `cli_chatbot`.
+//! It runs the `whisper` binary installed next to it, which chats when no
+//! command is given, so that both take the same arguments and never drift
+//! apart.
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let app_config = config::read_config::load_config()?;
+use std::process::Command;
 
-    let (comp_models, embed_models) = load_models(&app_config)?;
+fn main() -> anyhow::Result<()> {
+    let whisper =
+        std::env::current_exe()?.with_file_name(format!("whisper{}", std::env::consts::EXE_SUFFIX));
+    let mut command = Command::new(&whisper);
+    command.args(std::env::args_os().skip(1));
 
-    if let Some(mcp_servers) = &app_config.mcp_servers {
-        let mcp_manager = McpManagerBuilder::new()
-            .load_config(&mcp_servers)
-            .build()
-            .await?;
+    // Replace this process, so that Ctrl-C and the exit code are whisper's
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
 
-        let tool_set = mcp_manager.get_tool_set().await?;
-
-        let embed_model = &embed_models[0];
-        let comp_handle = CompletionModelHandle {
-            inner: comp_models[0].clone(),
-        };
+        let e = command.exec();
+        Err(anyhow::anyhow!("Failed to run {}: {e}", whisper.display()))
     }
 
-    Ok(())
+    #[cfg(not(unix))]
+    {
+        let status = command
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {e}", whisper.display()))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
 }
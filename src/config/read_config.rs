@@ -1,23 +1,117 @@
-use crate::mcp::transport::TransportConfig;
+use crate::agent::{
+    attachment::AttachmentConfig,
+    context::ContextConfig,
+    profile::AgentProfile,
+    structured::StructuredOutput,
+    transcript::DEFAULT_SESSION_DIR,
+    usage::{DEFAULT_USAGE_DIR, ModelPrice},
+};
+use crate::mcp::{builtin::BuiltinToolsConfig, proxy::ProxyConfig, transport::TransportConfig};
+use crate::rag::store::VectorStoreConfig;
 use crate::secure::{self, load_key_from_env};
-use config::{Config, Environment, File};
+use config::{Config, ConfigError, Environment, File};
 use dotenvy::dotenv;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::HashMap, path::Path};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub models: Vec<ModelConfig>,
     pub mcp_servers: Option<Vec<TransportConfig>>,
+    /// Tools run by whisper itself, served as the MCP server `builtin`
+    #[serde(default)]
+    pub builtin_tools: BuiltinToolsConfig,
+    pub agents: Option<Vec<AgentProfile>>,
+    pub knowledge: Option<KnowledgeConfig>,
+    /// Backend of the tool and document indexes
+    #[serde(default)]
+    pub vector_store: VectorStoreConfig,
+    /// Tools served by `whisper mcp --proxy`
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    #[serde(default)]
+    pub chat: ChatConfig,
+    /// Custom variables of the preamble templates
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+}
+
+/// Behavior of the interactive sessions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChatConfig {
+    /// Keep the partial answer in the history when it is interrupted
+    #[serde(default = "default_true")]
+    pub keep_interrupted: bool,
+    /// Directory of the daily usage logs, empty to disable them
+    #[serde(default = "default_usage_dir")]
+    pub usage_dir: String,
+    /// Directory of the session transcripts, empty to disable them
+    #[serde(default = "default_session_dir")]
+    pub session_dir: String,
+    /// How the history is fitted in the context window of the models
+    #[serde(default)]
+    pub context: ContextConfig,
+    /// Limits of the files attached with `@path`
+    #[serde(default)]
+    pub attachments: AttachmentConfig,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            keep_interrupted: true,
+            usage_dir: default_usage_dir(),
+            session_dir: default_session_dir(),
+            context: ContextConfig::default(),
+            attachments: AttachmentConfig::default(),
+        }
+    }
+}
+
+fn default_usage_dir() -> String {
+    DEFAULT_USAGE_DIR.to_string()
+}
+
+fn default_session_dir() -> String {
+    DEFAULT_SESSION_DIR.to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Knowledge base built by `/ingest`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KnowledgeConfig {
+    /// Number of chunks retrieved for every prompt
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+}
+
+fn default_samples() -> usize {
+    3
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ModelConfig {
+    #[serde(default)]
     pub base_url: String,
+    /// Encrypted api key, could be empty for local models
+    #[serde(default)]
     pub api_key: String,
     #[serde(deserialize_with = "to_lowercase")]
     pub provider: String,
     pub model_name: String,
     pub model_type: ModelType,
+    /// Dimensions of the embedding vectors, only used by local models
+    pub dimensions: Option<usize>,
+    /// Price used to estimate the cost of the usage
+    pub price: Option<ModelPrice>,
+    /// Tokens the model accepts, the history is cut to fit if set
+    pub context_window: Option<usize>,
+    /// How answers matching a JSON schema are asked
+    #[serde(default)]
+    pub structured_output: StructuredOutput,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -37,21 +131,47 @@ where
 }
 
 pub fn load_config() -> Result<AppConfig, config::ConfigError> {
+    load_config_with(File::with_name("config.toml").required(false))
+}
+
+/// Load config from the given file, which must exist
+pub fn load_config_from(path: &Path) -> Result<AppConfig, config::ConfigError> {
+    load_config_with(File::from(path).required(true))
+}
+
+fn load_config_with<F>(file: F) -> Result<AppConfig, config::ConfigError>
+where
+    F: config::Source + Send + Sync + 'static,
+{
     dotenv().ok();
 
     let builder = Config::builder()
-        .add_source(File::with_name("config.toml").required(false))
+        .add_source(file)
         .add_source(Environment::with_prefix("WHISPER").separator("__"));
 
     let config = builder.build()?;
 
-    let key_bytes = load_key_from_env("ENCRYPT_KEY").expect("Get key bytes error!");
     let mut config = config.try_deserialize::<AppConfig>()?;
     let mut valid_models = Vec::new();
+    // Loaded for the first api_key, so that local models need no key
+    let mut key_bytes = None;
 
     for mut model in config.models {
+        // Local models need no api_key
+        if model.api_key.is_empty() {
+            valid_models.push(model);
+            continue;
+        }
+
+        let key = match key_bytes {
+            Some(key) => key,
+            None => *key_bytes.insert(load_key_from_env("ENCRYPT_KEY").map_err(|e| {
+                ConfigError::Message(format!("Failed to load the key of the api keys: {e}"))
+            })?),
+        };
+
         // Decrypt api_key
-        match secure::aes::decrypt(&model.api_key, &key_bytes) {
+        match secure::aes::decrypt(&model.api_key, &key) {
             Ok(decrypted) => {
                 model.api_key = decrypted;
                 valid_models.push(model);
@@ -2,3 +2,5 @@ pub mod secure;
 pub mod agent;
 pub mod config;
 pub mod mcp;
+pub mod rag;
+pub mod server;
@@ -1,35 +1,119 @@
-// pub mod chat;
-// pub mod config;
-// pub mod mcp;
-use whisper::config;
-use whisper::mcp;
-
-use std::env;
-
-use rig::{
-    agent::AgentBuilder,
-    client::{CompletionClient, EmbeddingsClient},
-    embeddings::EmbeddingsBuilder,
-    providers::openai,
-    vector_store::in_memory_store::InMemoryVectorStore,
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
+
+use clap::{Parser, Subcommand};
+use tokio::io::AsyncReadExt;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use whisper::{
+    agent::{
+        bootstrap::{Bootstrap, ProfileSession},
+        cli_chat::{CliFrontend, DEFAULT_HISTORY_FILE},
+        export::SessionExport,
+        jsonl::JsonlFrontend,
+        oneshot::CaptureSink,
+        transcript::Transcript,
+        tui::TuiFrontend,
+    },
+    config,
+    mcp::proxy::ToolProxy,
+    server::{self, mcp::WhisperMcpServer},
+};
+
+#[derive(Parser)]
+#[command(name = "whisper")]
+#[command(about = "Chat with LLM agents using MCP tools", long_about = None)]
+struct Cli {
+    /// Path of the config file
+    #[arg(short, long, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// Model used by every agent, overrides the profiles
+    #[arg(short, long)]
+    model: Option<String>,
+
+    /// Name of the agent profile to start with
+    #[arg(short, long)]
+    agent: Option<String>,
+
+    /// Go on with a session exported as JSON by `/export`
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Directory of the daily log files
+    #[arg(long, default_value = "logs")]
+    log_dir: PathBuf,
+
+    /// Start the interactive chat if no command is given
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    #[command(about = "Answer a single prompt and print only the final answer")]
+    Ask {
+        /// The prompt, placed before the content of stdin if both are given
+        prompt: Option<String>,
+
+        /// Read the prompt from stdin
+        #[arg(long)]
+        stdin: bool,
+
+        /// Print the answer, usage and tool calls as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Answer with JSON matching the JSON Schema in this file, printed alone
+        #[arg(long, conflicts_with = "json")]
+        schema: Option<PathBuf>,
+
+        /// Times an answer not matching the schema is sent back to be fixed
+        #[arg(long, default_value_t = 2, requires = "schema")]
+        retries: usize,
 
-use crate::{chat::SessionBuilder, mcp::manager::McpManagerBuilder};
+        /// Attach the files mentioned as `@path` in the prompt
+        #[arg(long)]
+        attach: bool,
+    },
+    #[command(about = "Chat in a full-screen terminal interface")]
+    Tui,
+    #[command(about = "Chat over stdin and stdout with JSON lines, for other programs")]
+    Jsonl,
+    #[command(about = "Serve the agents with an OpenAI compatible chat completions API")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+    #[command(about = "Serve the agent as an MCP server, over stdio by default")]
+    Mcp {
+        /// Serve streamable HTTP on this address instead of stdio
+        #[arg(long)]
+        http: Option<SocketAddr>,
+
+        /// List the merged MCP tools next to `ask`, as configured in `[proxy]`
+        #[arg(long)]
+        export_tools: bool,
+
+        /// Serve only the merged MCP tools, as configured in `[proxy]`
+        #[arg(long, conflicts_with = "export_tools")]
+        proxy: bool,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     // Load environment file
     dotenvy::dotenv().ok();
 
-    println!("Read config...");
-    let app_config = config::read_config::load_config()?;
-    println!("Get {app_config:#?}");
-
+    let cli = Cli::parse();
 
     let file_appender = RollingFileAppender::new(
         Rotation::DAILY,
-        "logs",
+        &cli.log_dir,
         format!("{}.log", env!("CARGO_CRATE_NAME")),
     );
     tracing_subscriber::fmt()
@@ -42,57 +126,135 @@ async fn main() -> Result<(), anyhow::Error> {
         .with_ansi(false)
         .init();
 
-    let mcp_manager = McpManagerBuilder::new()
-        .add_sse("Weather", "http://localhost:8000/sse")
-        .build()
-        .await?;
+    let app_config = config::read_config::load_config_from(&cli.config)?;
+    tracing::info!("Load config from {}", cli.config.display());
+
+    let bootstrap = Bootstrap::load(&app_config, cli.model.as_deref()).await?;
+    let agent = cli.agent.as_deref();
+    let resume = cli.resume.as_deref();
+
+    match cli.command {
+        None => {
+            let mut frontend = CliFrontend::new()?.history_file(DEFAULT_HISTORY_FILE);
+            session(&bootstrap, agent, resume, bootstrap.transcript())?
+                .run(&mut frontend)
+                .await
+        }
+        Some(Commands::Tui) => {
+            let session = session(&bootstrap, agent, resume, bootstrap.transcript())?;
+            let profile = match agent {
+                Some(name) => bootstrap.factory.profile(name),
+                None => Some(bootstrap.factory.default_profile()),
+            };
+            let label = profile
+                .map(|p| {
+                    let model = bootstrap.factory.model_name(p).unwrap_or("no model");
+                    format!("{} · {model}", p.name)
+                })
+                .unwrap_or_default();
 
-    let tool_set = mcp_manager.get_tool_set().await?;
+            let mut frontend = TuiFrontend::new(label, &bootstrap.mcp_manager)?;
+            session.run(&mut frontend).await
+        }
+        Some(Commands::Serve { addr }) => server::serve(bootstrap, addr).await,
+        Some(Commands::Mcp {
+            http,
+            export_tools,
+            proxy,
+        }) => {
+            let tool_proxy = ToolProxy::new(&app_config.proxy, bootstrap.factory.mcp_tools());
+            let mut mcp_server = WhisperMcpServer::new(Arc::new(bootstrap));
+            if export_tools || proxy {
+                mcp_server = mcp_server.proxy(tool_proxy);
+            }
+            if proxy {
+                mcp_server = mcp_server.proxy_only();
+            }
 
-    let client = match openai::Client::builder(env::var("PROVIDER_API_KEY")?.as_str())
-        .base_url(env::var("PROVIDER_BASE_URL")?.as_str())
-        .build()
-    {
-        Ok(c) => {
-            tracing::info!("Client initialized successfully");
-            c
+            match http {
+                Some(addr) => server::mcp::serve_http(mcp_server, addr).await,
+                None => server::mcp::serve_stdio(mcp_server).await,
+            }
         }
-        Err(e) => {
-            tracing::error!("Failed to build Client: {}", e);
-            return Err(anyhow::anyhow!("Client build error: {}", e));
+        Some(Commands::Jsonl) => {
+            let mut frontend = JsonlFrontend::new(
+                tokio::io::BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+            );
+            session(&bootstrap, agent, resume, bootstrap.transcript())?
+                .attach_mentions(false)
+                .run(&mut frontend)
+                .await
         }
-    };
+        Some(Commands::Ask {
+            prompt,
+            stdin,
+            json,
+            schema,
+            retries,
+            attach,
+        }) => {
+            let mut session =
+                session(&bootstrap, agent, resume, Transcript::new())?.attach_mentions(attach);
+            let prompt = session.prompt(&read_prompt(prompt, stdin).await?)?;
 
-    let chat_model = client
-        .completion_model(env::var("MODEL_NAME")?.as_str())
-        .completions_api();
+            if let Some(path) = schema {
+                let schema = std::fs::read_to_string(&path).map_err(|e| {
+                    anyhow::anyhow!("Failed to read schema file {}: {e}", path.display())
+                })?;
+                let schema: serde_json::Value = serde_json::from_str(&schema)
+                    .map_err(|e| anyhow::anyhow!("Invalid schema {}: {e}", path.display()))?;
 
-    let embed_model = client.embedding_model("text-embedding-v3");
-    let embeddings = EmbeddingsBuilder::new(embed_model.clone())
-        .documents(tool_set.schemas()?)?
-        .build()
-        .await?;
+                let answer = session.ask_structured(prompt, &schema, retries).await?;
+                println!("{}", serde_json::to_string_pretty(&answer)?);
+                return Ok(());
+            }
 
-    let store = InMemoryVectorStore::from_documents_with_id_f(embeddings, |tool| tool.name.clone());
+            let mut sink = CaptureSink::new();
+            session.ask(prompt, &mut sink).await?;
+            let report = sink.into_report();
 
-    let index = store.index(embed_model);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report.answer.trim());
+            }
 
-    let agent = AgentBuilder::new(chat_model)
-        .preamble(
-            "You are a helpful assistant.
-When answering questions, first write out your reasoning step by step,
-then give the final concise answer.  Keep the explanation short but clear.
-",
-        )
-        .temperature(0.6)
-        .dynamic_tools(2, index, tool_set)
-        .build();
+            match report.errors.first() {
+                Some(e) => Err(anyhow::anyhow!("{e}")),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+/// Session of the chosen agent writing `transcript`, going on with the
+/// exported one if given
+fn session(
+    bootstrap: &Bootstrap,
+    agent: Option<&str>,
+    resume: Option<&Path>,
+    transcript: Transcript,
+) -> anyhow::Result<ProfileSession> {
+    let session = bootstrap.session(agent)?.transcript(transcript);
+
+    match resume {
+        Some(path) => session.resume(&SessionExport::load(path)?),
+        None => Ok(session),
+    }
+}
 
-    let conversation = SessionBuilder::new()
-        .agent(agent)
-        .multi_turn_depth(4)
-        .show_usage()
-        .build();
+/// Join the prompt argument and stdin
+async fn read_prompt(prompt: Option<String>, stdin: bool) -> anyhow::Result<String> {
+    let mut input = String::new();
+    if stdin {
+        tokio::io::stdin().read_to_string(&mut input).await?;
+    }
 
-    conversation.run().await
+    match (prompt, input.trim()) {
+        (None, "") => Err(anyhow::anyhow!("Give a prompt or use --stdin")),
+        (Some(prompt), "") => Ok(prompt),
+        (None, input) => Ok(input.to_string()),
+        (Some(prompt), input) => Ok(format!("{prompt}\n\n{input}")),
+    }
 }
@@ -0,0 +1,854 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use chrono::{Local, Utc};
+use regex_automata::meta::Regex;
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use walkdir::WalkDir;
+
+use super::{sandbox::SandboxProfile, tool_adaptor::McpToolAdaptor};
+
+/// Server name of the built-in tools, e.g. in the `builtin/shell` policy pattern
+pub const BUILTIN_SERVER: &str = "builtin";
+
+/// Lines listed by `grep` at most
+const MAX_MATCHES: usize = 200;
+
+/// Tools run by whisper itself, each one is disabled unless set
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BuiltinToolsConfig {
+    #[serde(default)]
+    pub read_file: bool,
+    #[serde(default)]
+    pub list_dir: bool,
+    #[serde(default)]
+    pub grep: bool,
+    #[serde(default)]
+    pub shell: bool,
+    #[serde(default)]
+    pub time: bool,
+    /// Directory the tools are confined to, the current one if empty
+    #[serde(default)]
+    pub root: String,
+    /// Seconds before a shell command is killed
+    #[serde(default = "default_shell_timeout")]
+    pub shell_timeout: u64,
+    /// Restrictions of the shell commands, which run in `root` unless set
+    #[serde(default)]
+    pub shell_sandbox: SandboxProfile,
+    /// Bytes of output returned by a tool, the rest is cut
+    #[serde(default = "default_max_output")]
+    pub max_output: usize,
+}
+
+impl Default for BuiltinToolsConfig {
+    fn default() -> Self {
+        Self {
+            read_file: false,
+            list_dir: false,
+            grep: false,
+            shell: false,
+            time: false,
+            root: String::new(),
+            shell_timeout: default_shell_timeout(),
+            shell_sandbox: SandboxProfile::default(),
+            max_output: default_max_output(),
+        }
+    }
+}
+
+fn default_shell_timeout() -> u64 {
+    30
+}
+
+fn default_max_output() -> usize {
+    64 * 1024
+}
+
+/// The enabled tools
+pub async fn get_tools(config: &BuiltinToolsConfig) -> anyhow::Result<Vec<McpToolAdaptor>> {
+    let mut tools = Vec::new();
+    if !(config.read_file || config.list_dir || config.grep || config.shell || config.time) {
+        return Ok(tools);
+    }
+
+    let root = match config.root.as_str() {
+        "" => ".",
+        root => root,
+    };
+    let root = fs::canonicalize(root)
+        .map_err(|e| anyhow::anyhow!("Invalid root of the built-in tools `{root}`: {e}"))?;
+    if !root.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Root of the built-in tools {} is not a directory",
+            root.display()
+        ));
+    }
+    let sandbox = Sandbox {
+        root,
+        max_output: config.max_output,
+    };
+
+    if config.read_file {
+        tools.push(McpToolAdaptor::native(ReadFile(sandbox.clone())).await);
+    }
+    if config.list_dir {
+        tools.push(McpToolAdaptor::native(ListDir(sandbox.clone())).await);
+    }
+    if config.grep {
+        tools.push(McpToolAdaptor::native(Grep(sandbox.clone())).await);
+    }
+    if config.shell {
+        tools.push(
+            McpToolAdaptor::native(Shell {
+                sandbox,
+                timeout: Duration::from_secs(config.shell_timeout),
+                profile: config.shell_sandbox.clone(),
+            })
+            .await,
+        );
+    }
+    if config.time {
+        tools.push(McpToolAdaptor::native(CurrentTime).await);
+    }
+
+    Ok(tools)
+}
+
+/// Failure reported to the model as the result of the call
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ToolFailure(String);
+
+/// Directory the tools may touch and size of their output
+#[derive(Debug, Clone)]
+struct Sandbox {
+    root: PathBuf,
+    max_output: usize,
+}
+
+impl Sandbox {
+    /// `path` relative to the root, refused if it leads outside of it
+    fn resolve(&self, path: &str) -> Result<PathBuf, ToolFailure> {
+        let resolved = fs::canonicalize(self.root.join(path))
+            .map_err(|e| ToolFailure(format!("Invalid path `{path}`: {e}")))?;
+
+        if !resolved.starts_with(&self.root) {
+            return Err(ToolFailure(format!(
+                "`{path}` is outside of the allowed directory"
+            )));
+        }
+        Ok(resolved)
+    }
+
+    /// Path shown to the model, relative to the root
+    fn display(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.root) {
+            Ok(path) if path.as_os_str().is_empty() => ".".to_string(),
+            Ok(path) => path.display().to_string(),
+            Err(_) => path.display().to_string(),
+        }
+    }
+
+    /// The first `max_output` bytes of `text`, with a note if it was cut
+    fn cut(&self, mut text: String) -> String {
+        if text.len() <= self.max_output {
+            return text;
+        }
+
+        let mut end = self.max_output;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str(&format!("\n[cut after {end} bytes]"));
+        text
+    }
+}
+
+/// Reader of a file, if it starts like text: UTF-8 without any NUL
+fn open_text(path: &Path) -> Option<BufReader<File>> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let start = reader.fill_buf().ok()?;
+
+    let utf8 = match std::str::from_utf8(start) {
+        Ok(_) => true,
+        // The buffer cut a character in two
+        Err(e) => e.error_len().is_none(),
+    };
+    (utf8 && !start.contains(&0)).then_some(reader)
+}
+
+/// Lines of a text file, each one cut after `max` bytes, so that only what is
+/// used is read. They end at the first line which is not text.
+fn text_lines(mut reader: BufReader<File>, max: usize) -> impl Iterator<Item = String> {
+    std::iter::from_fn(move || {
+        let mut line = Vec::new();
+        let read = (&mut reader)
+            .take(max as u64)
+            .read_until(b'\n', &mut line)
+            .ok()?;
+        if read == 0 {
+            return None;
+        }
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        } else if read == max {
+            reader.skip_until(b'\n').ok()?;
+        }
+        if line.contains(&0) {
+            return None;
+        }
+
+        match String::from_utf8(line) {
+            Ok(line) => Some(line),
+            // The limit cut a character in two
+            Err(e) if e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut line = e.into_bytes();
+                line.truncate(valid);
+                String::from_utf8(line).ok()
+            }
+            Err(_) => None,
+        }
+    })
+}
+
+/// Run file system work on the blocking threads, the async ones may drive
+/// every session of the server
+async fn blocking<F>(work: F) -> Result<String, ToolFailure>
+where
+    F: FnOnce() -> Result<String, ToolFailure> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| ToolFailure(format!("The tool failed: {e}")))?
+}
+
+fn default_path() -> String {
+    ".".to_string()
+}
+
+pub struct ReadFile(Sandbox);
+
+#[derive(Deserialize)]
+pub struct ReadFileArgs {
+    path: String,
+    /// First line read, from 1
+    offset: Option<usize>,
+    /// Number of lines read
+    limit: Option<usize>,
+}
+
+impl Tool for ReadFile {
+    const NAME: &'static str = "read_file";
+
+    type Error = ToolFailure;
+    type Args = ReadFileArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Read a text file, optionally only some of its lines".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Path of the file"},
+                    "offset": {"type": "integer", "description": "First line to read, from 1"},
+                    "limit": {"type": "integer", "description": "Number of lines to read"}
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let sandbox = self.0.clone();
+        blocking(move || read_file(&sandbox, args)).await
+    }
+}
+
+fn read_file(sandbox: &Sandbox, args: ReadFileArgs) -> Result<String, ToolFailure> {
+    let path = sandbox.resolve(&args.path)?;
+    let reader = open_text(&path)
+        .ok_or_else(|| ToolFailure(format!("`{}` is not a text file", args.path)))?;
+
+    let lines = text_lines(reader, sandbox.max_output)
+        .skip(args.offset.unwrap_or(1).saturating_sub(1))
+        .take(args.limit.unwrap_or(usize::MAX));
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(&line);
+        text.push('\n');
+        // Enough to be cut
+        if text.len() > sandbox.max_output {
+            break;
+        }
+    }
+
+    Ok(sandbox.cut(text))
+}
+
+pub struct ListDir(Sandbox);
+
+#[derive(Deserialize)]
+pub struct ListDirArgs {
+    #[serde(default = "default_path")]
+    path: String,
+}
+
+impl Tool for ListDir {
+    const NAME: &'static str = "list_dir";
+
+    type Error = ToolFailure;
+    type Args = ListDirArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "List the entries of a directory, directories end with `/`".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Path of the directory, `.` by default"}
+                }
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let sandbox = self.0.clone();
+        blocking(move || list_dir(&sandbox, args)).await
+    }
+}
+
+fn list_dir(sandbox: &Sandbox, args: ListDirArgs) -> Result<String, ToolFailure> {
+    let path = sandbox.resolve(&args.path)?;
+    let entries = fs::read_dir(&path)
+        .map_err(|e| ToolFailure(format!("Failed to list `{}`: {e}", args.path)))?;
+
+    let mut lines = entries
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => format!("{name}/"),
+                Ok(metadata) => format!("{name} ({} bytes)", metadata.len()),
+                Err(_) => name,
+            }
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    Ok(sandbox.cut(lines.join("\n")))
+}
+
+pub struct Grep(Sandbox);
+
+#[derive(Deserialize)]
+pub struct GrepArgs {
+    pattern: String,
+    #[serde(default = "default_path")]
+    path: String,
+    #[serde(default)]
+    ignore_case: bool,
+}
+
+impl Tool for Grep {
+    const NAME: &'static str = "grep";
+
+    type Error = ToolFailure;
+    type Args = GrepArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Search a regular expression in the text files under a path, \
+                hidden files are skipped"
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {"type": "string", "description": "Regular expression"},
+                    "path": {"type": "string", "description": "File or directory, `.` by default"},
+                    "ignore_case": {"type": "boolean"}
+                },
+                "required": ["pattern"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let sandbox = self.0.clone();
+        blocking(move || grep(&sandbox, args)).await
+    }
+}
+
+fn grep(sandbox: &Sandbox, args: GrepArgs) -> Result<String, ToolFailure> {
+    let path = sandbox.resolve(&args.path)?;
+    let regex = Regex::builder()
+        .syntax(regex_automata::util::syntax::Config::new().case_insensitive(args.ignore_case))
+        .build(&args.pattern)
+        .map_err(|e| ToolFailure(format!("Invalid pattern: {e}")))?;
+
+    let files = WalkDir::new(&path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file());
+
+    let mut matches = Vec::new();
+    'files: for file in files {
+        let Some(reader) = open_text(file.path()) else {
+            continue;
+        };
+        for (number, line) in text_lines(reader, sandbox.max_output).enumerate() {
+            if !regex.is_match(&line) {
+                continue;
+            }
+            if matches.len() == MAX_MATCHES {
+                matches.push(format!("[stopped after {MAX_MATCHES} lines]"));
+                break 'files;
+            }
+            matches.push(format!(
+                "{}:{}: {line}",
+                sandbox.display(file.path()),
+                number + 1
+            ));
+        }
+    }
+
+    if matches.is_empty() {
+        return Ok("No match".to_string());
+    }
+    Ok(sandbox.cut(matches.join("\n")))
+}
+
+pub struct Shell {
+    sandbox: Sandbox,
+    timeout: Duration,
+    profile: SandboxProfile,
+}
+
+#[derive(Deserialize)]
+pub struct ShellArgs {
+    command: String,
+}
+
+impl Tool for Shell {
+    const NAME: &'static str = "shell";
+
+    type Error = ToolFailure;
+    type Args = ShellArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Run a command with `sh -c` in the working directory, it is killed after {} seconds",
+                self.timeout.as_secs()
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string"}
+                },
+                "required": ["command"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let mut command = tokio::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&args.command)
+            .current_dir(&self.sandbox.root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Its own process group, so that background processes are killed too
+        #[cfg(unix)]
+        command.process_group(0);
+        self.profile
+            .apply(&mut command)
+            .map_err(|e| ToolFailure(e.to_string()))?;
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| ToolFailure(format!("Failed to run the command: {e}")))?;
+        let group = child.id();
+        let max = self.sandbox.max_output;
+        let stdout = read_capped(child.stdout.take(), max);
+        let stderr = read_capped(child.stderr.take(), max);
+        let output = tokio::time::timeout(self.timeout, async {
+            let (status, stdout, stderr) = tokio::join!(child.wait(), stdout, stderr);
+            std::io::Result::Ok((status?, stdout?, stderr?))
+        })
+        .await;
+        // Nothing started by the command outlives it
+        kill_group(group);
+
+        let (status, stdout, stderr) = output
+            .map_err(|_| {
+                ToolFailure(format!(
+                    "The command was killed after {} seconds",
+                    self.timeout.as_secs()
+                ))
+            })?
+            .map_err(|e| ToolFailure(format!("Failed to run the command: {e}")))?;
+
+        let mut text = match status.code() {
+            Some(code) => format!("exit code: {code}\n"),
+            None => "killed by a signal\n".to_string(),
+        };
+        text.push_str(&String::from_utf8_lossy(&stdout));
+        if !stderr.is_empty() {
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str("stderr:\n");
+            text.push_str(&String::from_utf8_lossy(&stderr));
+        }
+
+        Ok(self.sandbox.cut(text))
+    }
+}
+
+/// The first `max` bytes written to a pipe. The rest is read and dropped, so
+/// that the command is not blocked on a full pipe.
+async fn read_capped(pipe: Option<impl AsyncRead + Unpin>, max: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let Some(mut pipe) = pipe else {
+        return Ok(bytes);
+    };
+
+    (&mut pipe).take(max as u64).read_to_end(&mut bytes).await?;
+    tokio::io::copy(&mut pipe, &mut tokio::io::sink()).await?;
+
+    Ok(bytes)
+}
+
+/// Kill the process group led by `leader`
+#[cfg(unix)]
+fn kill_group(leader: Option<u32>) {
+    let Some(leader) = leader.and_then(|pid| libc::pid_t::try_from(pid).ok()) else {
+        return;
+    };
+    // SAFETY: sending a signal has no memory effect, a group which is gone
+    // only makes it fail
+    unsafe {
+        libc::kill(-leader, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_leader: Option<u32>) {}
+
+pub struct CurrentTime;
+
+#[derive(Deserialize)]
+pub struct CurrentTimeArgs {}
+
+impl Tool for CurrentTime {
+    const NAME: &'static str = "current_time";
+
+    type Error = ToolFailure;
+    type Args = CurrentTimeArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Current local date and time, with the day of the week".to_string(),
+            parameters: json!({"type": "object", "properties": {}}),
+        }
+    }
+
+    async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let now = Local::now();
+        Ok(format!(
+            "{} ({}), {} UTC",
+            now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            now.format("%A"),
+            Utc::now().format("%Y-%m-%d %H:%M:%S")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory unique to the test, with its sandbox
+    fn sandbox(name: &str, max_output: usize) -> Sandbox {
+        let dir =
+            std::env::temp_dir().join(format!("whisper-builtin-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        Sandbox {
+            root: fs::canonicalize(dir).unwrap(),
+            max_output,
+        }
+    }
+
+    fn lines(sandbox: &Sandbox, file: &str, content: &[u8], max: usize) -> Vec<String> {
+        let path = sandbox.root.join(file);
+        fs::write(&path, content).unwrap();
+        text_lines(BufReader::new(File::open(path).unwrap()), max).collect()
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_root() {
+        let sandbox = sandbox("inside", 1024);
+        fs::create_dir(sandbox.root.join("sub")).unwrap();
+        fs::write(sandbox.root.join("a.txt"), "a").unwrap();
+
+        assert_eq!(sandbox.resolve(".").unwrap(), sandbox.root);
+        assert_eq!(
+            sandbox.resolve("sub/../a.txt").unwrap(),
+            sandbox.root.join("a.txt")
+        );
+        assert_eq!(sandbox.display(&sandbox.root.join("sub")), "sub");
+        assert!(sandbox.resolve("missing.txt").is_err());
+    }
+
+    #[test]
+    fn refuses_to_leave_the_root() {
+        let sandbox = sandbox("escape", 1024);
+        fs::create_dir(sandbox.root.join("sub")).unwrap();
+        let parent = format!("{}/..", sandbox.root.display());
+
+        for path in ["..", "../..", "sub/../..", "/", "/etc/passwd", &parent] {
+            assert!(sandbox.resolve(path).is_err(), "`{path}` was resolved");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_leading_out() {
+        let outside = sandbox("outside", 1024);
+        fs::write(outside.root.join("secret.txt"), "secret").unwrap();
+        let sandbox = sandbox("symlink", 1024);
+        std::os::unix::fs::symlink(outside.root.join("secret.txt"), sandbox.root.join("file"))
+            .unwrap();
+        std::os::unix::fs::symlink(&outside.root, sandbox.root.join("dir")).unwrap();
+        std::os::unix::fs::symlink("file", sandbox.root.join("inner")).unwrap();
+
+        assert!(sandbox.resolve("file").is_err());
+        assert!(sandbox.resolve("dir/secret.txt").is_err());
+        assert!(sandbox.resolve("dir").is_err());
+        assert!(sandbox.resolve("inner").is_err());
+    }
+
+    #[test]
+    fn cuts_at_a_character_boundary() {
+        let sandbox = Sandbox {
+            root: PathBuf::new(),
+            max_output: 4,
+        };
+
+        assert_eq!(sandbox.cut("abcd".to_string()), "abcd");
+        assert_eq!(
+            sandbox.cut("abcde".to_string()),
+            "abcd\n[cut after 4 bytes]"
+        );
+        // `é` takes the bytes 3 and 4
+        assert_eq!(sandbox.cut("abcéé".to_string()), "abc\n[cut after 3 bytes]");
+    }
+
+    #[test]
+    fn cuts_long_lines_at_a_character_boundary() {
+        let sandbox = sandbox("long-lines", 1024);
+
+        assert_eq!(
+            lines(&sandbox, "a.txt", "ééé\nabcdef\r\nxy".as_bytes(), 3),
+            ["é", "abc", "xy"]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_binary_line() {
+        let sandbox = sandbox("binary", 1024);
+
+        assert_eq!(lines(&sandbox, "nul.txt", b"a\nb\0c\nd\n", 1024), ["a"]);
+        assert_eq!(
+            lines(&sandbox, "latin1.txt", b"a\n\xe9t\xe9\nd\n", 1024),
+            ["a"]
+        );
+    }
+
+    #[test]
+    fn opens_only_text_files() {
+        let sandbox = sandbox("open", 1024);
+        fs::write(sandbox.root.join("text.txt"), "text").unwrap();
+        fs::write(sandbox.root.join("nul.bin"), b"ELF\0\x01").unwrap();
+        fs::write(sandbox.root.join("latin1.txt"), b"\xe9t\xe9").unwrap();
+
+        assert!(open_text(&sandbox.root.join("text.txt")).is_some());
+        assert!(open_text(&sandbox.root.join("nul.bin")).is_none());
+        assert!(open_text(&sandbox.root.join("latin1.txt")).is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_some_lines_of_a_file() {
+        let sandbox = sandbox("read", 1024);
+        fs::write(sandbox.root.join("a.txt"), "1\n2\n3\n4\n").unwrap();
+        fs::write(sandbox.root.join("a.bin"), b"\0\0\0").unwrap();
+        let tool = ReadFile(sandbox);
+
+        let args = |path: &str, offset, limit| ReadFileArgs {
+            path: path.to_string(),
+            offset,
+            limit,
+        };
+        assert_eq!(
+            tool.call(args("a.txt", None, None)).await.unwrap(),
+            "1\n2\n3\n4\n"
+        );
+        assert_eq!(
+            tool.call(args("a.txt", Some(2), Some(2))).await.unwrap(),
+            "2\n3\n"
+        );
+        assert!(tool.call(args("a.bin", None, None)).await.is_err());
+        assert!(tool.call(args("../a.txt", None, None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn lists_a_directory() {
+        let sandbox = sandbox("list", 1024);
+        fs::create_dir(sandbox.root.join("sub")).unwrap();
+        fs::write(sandbox.root.join("a.txt"), "abc").unwrap();
+
+        let listing = ListDir(sandbox)
+            .call(ListDirArgs {
+                path: ".".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(listing, "a.txt (3 bytes)\nsub/");
+    }
+
+    #[tokio::test]
+    async fn grep_stops_after_the_maximum_of_matches() {
+        let sandbox = sandbox("grep", 64 * 1024);
+        let text: String = (0..MAX_MATCHES + 50)
+            .map(|i| format!("match {i}\n"))
+            .collect();
+        fs::write(sandbox.root.join("a.txt"), text).unwrap();
+        fs::write(sandbox.root.join(".hidden"), "match\n").unwrap();
+        fs::write(sandbox.root.join("b.bin"), b"match\0").unwrap();
+        let tool = Grep(sandbox);
+
+        let output = tool
+            .call(GrepArgs {
+                pattern: "MATCH".to_string(),
+                path: ".".to_string(),
+                ignore_case: true,
+            })
+            .await
+            .unwrap();
+        let found: Vec<&str> = output.lines().collect();
+
+        assert_eq!(found.len(), MAX_MATCHES + 1);
+        assert_eq!(found[0], "a.txt:1: match 0");
+        assert_eq!(
+            found[MAX_MATCHES],
+            format!("[stopped after {MAX_MATCHES} lines]")
+        );
+
+        let output = tool
+            .call(GrepArgs {
+                pattern: "MATCH".to_string(),
+                path: ".".to_string(),
+                ignore_case: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(output, "No match");
+    }
+
+    #[cfg(unix)]
+    fn shell(sandbox: Sandbox, timeout: Duration) -> Shell {
+        Shell {
+            sandbox,
+            timeout,
+            profile: SandboxProfile::default(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn caps_the_output_of_a_command() {
+        let tool = shell(sandbox("shell-output", 100), Duration::from_secs(10));
+
+        let output = tool
+            .call(ShellArgs {
+                command: "head -c 10000000 /dev/zero | tr '\\0' x; echo oops >&2".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert!(output.starts_with("exit code: 0\nxxx"), "{output}");
+        assert!(output.ends_with("\n[cut after 100 bytes]"), "{output}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_timeout_kills_the_background_processes() {
+        let sandbox = sandbox("shell-timeout", 1024);
+        let root = sandbox.root.clone();
+        let tool = shell(sandbox, Duration::from_millis(300));
+
+        let result = tool
+            .call(ShellArgs {
+                command: "(sleep 1; touch late) & sleep 10".to_string(),
+            })
+            .await;
+
+        assert!(result.unwrap_err().to_string().contains("killed"));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!root.join("late").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn an_exit_kills_the_background_processes() {
+        let sandbox = sandbox("shell-exit", 1024);
+        let root = sandbox.root.clone();
+        let tool = shell(sandbox, Duration::from_secs(10));
+
+        let output = tool
+            .call(ShellArgs {
+                command: "(sleep 1; touch late) >/dev/null 2>&1 & echo started".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(output, "exit code: 0\nstarted\n");
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!root.join("late").exists());
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
-use super::tool_adaptor;
+use super::sandbox::SandboxProfile;
+use super::tool_adaptor::{self, McpToolAdaptor};
 use super::transport::{TransportConfig, start as start_transport};
 use rig::tool::ToolSet;
 use rmcp::{RoleClient, service::RunningService};
@@ -8,9 +9,34 @@ use rmcp::{RoleClient, service::RunningService};
 /// Manage all Mcp Clients
 pub struct McpManager {
     pub clients: HashMap<String, RunningService<RoleClient, ()>>,
+    /// Servers which failed to start, with the error
+    pub failed: HashMap<String, String>,
+}
+
+/// Whether a configured server is usable
+#[derive(Debug, Clone)]
+pub struct ServerHealth {
+    pub name: String,
+    pub connected: bool,
 }
 
 impl McpManager {
+    /// Health of every configured server, sorted by name
+    pub fn health(&self) -> Vec<ServerHealth> {
+        let connected = self.clients.iter().map(|(name, client)| ServerHealth {
+            name: name.clone(),
+            connected: !client.peer().is_transport_closed(),
+        });
+        let failed = self.failed.keys().map(|name| ServerHealth {
+            name: name.clone(),
+            connected: false,
+        });
+
+        let mut health: Vec<_> = connected.chain(failed).collect();
+        health.sort_by(|a, b| a.name.cmp(&b.name));
+        health
+    }
+
     pub async fn get_tool_set(&self) -> anyhow::Result<ToolSet> {
         let mut tool_set = ToolSet::default();
         let mut task = tokio::task::JoinSet::<anyhow::Result<_>>::new();
@@ -34,6 +60,31 @@ impl McpManager {
 
         Ok(tool_set)
     }
+
+    /// Get the tools grouped by server name
+    pub async fn get_tools(&self) -> HashMap<String, Vec<McpToolAdaptor>> {
+        let mut tools = HashMap::new();
+        let mut task = tokio::task::JoinSet::new();
+
+        for (name, client) in &self.clients {
+            let name = name.clone();
+            let server = client.peer().clone();
+            task.spawn(async move { (name, tool_adaptor::get_tools(server).await) });
+        }
+
+        for (name, result) in task.join_all().await {
+            match result {
+                Err(e) => {
+                    tracing::error!(server=%name, error=%e, "Failed to get tools");
+                }
+                Ok(server_tools) => {
+                    tools.insert(name, server_tools);
+                }
+            }
+        }
+
+        tools
+    }
 }
 
 /// Build McpManager
@@ -82,6 +133,7 @@ impl McpManagerBuilder {
         command: impl Into<String>,
         args: Vec<String>,
         envs: HashMap<String, String>,
+        sandbox: Option<SandboxProfile>,
     ) -> Self {
         self.server.push((
             name.clone(),
@@ -90,6 +142,7 @@ impl McpManagerBuilder {
                 command: command.into(),
                 args,
                 envs,
+                sandbox,
             },
         ));
 
@@ -106,7 +159,14 @@ impl McpManagerBuilder {
                     command,
                     args,
                     envs,
-                } => self.add_stdio(name.clone(), command, args.clone(), envs.clone()),
+                    sandbox,
+                } => self.add_stdio(
+                    name.clone(),
+                    command,
+                    args.clone(),
+                    envs.clone(),
+                    sandbox.clone(),
+                ),
             };
         }
 
@@ -115,27 +175,29 @@ impl McpManagerBuilder {
 
     pub async fn build(self) -> anyhow::Result<McpManager> {
         let mut clients = HashMap::new();
-        let mut task_set = tokio::task::JoinSet::<anyhow::Result<_>>::new();
+        let mut failed = HashMap::new();
+        let mut task_set = tokio::task::JoinSet::new();
 
         for server in &self.server {
             let (server_name, transport_config) = server.clone();
             task_set.spawn(async move {
-                let client = start_transport(transport_config).await?;
-                anyhow::Result::Ok((server_name.clone(), client))
+                let client = start_transport(transport_config).await;
+                (server_name, client)
             });
         }
 
         let start_up_result = task_set.join_all().await;
-        for result in start_up_result {
+        for (name, result) in start_up_result {
             match result {
-                Ok((name, client)) => {
+                Ok(client) => {
                     clients.insert(name, client);
                 }
                 Err(e) => {
                     eprintln!("Failed to start server: {e:?}");
+                    failed.insert(name, e.to_string());
                 }
             }
         }
-        Ok(McpManager { clients })
+        Ok(McpManager { clients, failed })
     }
 }
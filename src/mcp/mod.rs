@@ -1,3 +1,7 @@
+pub mod builtin;
 pub mod manager;
+pub mod policy;
+pub mod proxy;
+pub mod sandbox;
 pub mod tool_adaptor;
 pub mod transport;
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens when a tool is called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalPolicy {
+    /// Call the tool directly
+    #[default]
+    Allow,
+    /// Ask the user before every call
+    Ask,
+    /// Never call the tool, it is hidden as well
+    Deny,
+}
+
+/// Policy of the tools matching `tool`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolRule {
+    /// Pattern of `server/tool`, `*` matches any characters
+    pub tool: String,
+    pub policy: ApprovalPolicy,
+}
+
+/// Which MCP tools are visible and how they are approved
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ToolPolicy {
+    /// Patterns of `server/tool` to keep, all tools if empty
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Patterns of `server/tool` to hide, checked after `include`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// The first matching rule wins, tools without rule are allowed
+    #[serde(default)]
+    pub rules: Vec<ToolRule>,
+}
+
+impl ToolPolicy {
+    /// Policy of a tool, [`ApprovalPolicy::Deny`] if it is filtered out
+    pub fn policy(&self, server: &str, tool: &str) -> ApprovalPolicy {
+        let path = format!("{server}/{tool}");
+
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| wildcard_match(p, &path));
+        if !included || self.exclude.iter().any(|p| wildcard_match(p, &path)) {
+            return ApprovalPolicy::Deny;
+        }
+
+        self.rules
+            .iter()
+            .find(|rule| wildcard_match(&rule.tool, &path))
+            .map(|rule| rule.policy)
+            .unwrap_or_default()
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any characters
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_without_wildcard() {
+        assert!(wildcard_match("fs/read", "fs/read"));
+        assert!(!wildcard_match("fs/read", "fs/reader"));
+        assert!(!wildcard_match("fs/read", "fs/rea"));
+        assert!(wildcard_match("", ""));
+    }
+
+    #[test]
+    fn matches_with_wildcards() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "fs/read"));
+        assert!(wildcard_match("fs/*", "fs/read"));
+        assert!(wildcard_match("*/read", "fs/read"));
+        assert!(wildcard_match("fs/*_file", "fs/read_file"));
+        assert!(wildcard_match("*/delete_*", "db/delete_row"));
+        assert!(wildcard_match("a*b*c", "abc"));
+        assert!(!wildcard_match("fs/*", "web/fetch"));
+        assert!(!wildcard_match("*_file", "fs/read_files"));
+    }
+
+    #[test]
+    fn prefix_and_suffix_may_not_overlap() {
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(wildcard_match("ab*ba", "abba"));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let policy = ToolPolicy {
+            include: Vec::new(),
+            exclude: vec!["db/drop_*".to_string()],
+            rules: vec![
+                ToolRule {
+                    tool: "db/select".to_string(),
+                    policy: ApprovalPolicy::Allow,
+                },
+                ToolRule {
+                    tool: "db/*".to_string(),
+                    policy: ApprovalPolicy::Ask,
+                },
+            ],
+        };
+
+        assert_eq!(policy.policy("db", "select"), ApprovalPolicy::Allow);
+        assert_eq!(policy.policy("db", "insert"), ApprovalPolicy::Ask);
+        assert_eq!(policy.policy("db", "drop_table"), ApprovalPolicy::Deny);
+        assert_eq!(policy.policy("fs", "read"), ApprovalPolicy::Allow);
+    }
+
+    #[test]
+    fn include_hides_the_other_tools() {
+        let policy = ToolPolicy {
+            include: vec!["fs/*".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(policy.policy("fs", "read"), ApprovalPolicy::Allow);
+        assert_eq!(policy.policy("web", "fetch"), ApprovalPolicy::Deny);
+    }
+}
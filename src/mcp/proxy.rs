@@ -0,0 +1,87 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rmcp::model::Tool as McpTool;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    policy::{ApprovalPolicy, ToolPolicy},
+    tool_adaptor::McpToolAdaptor,
+};
+
+/// How the tools of all MCP servers are served as a single one
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyConfig {
+    /// Joins the server and tool names, e.g. `github__create_issue`
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    #[serde(flatten)]
+    pub policy: ToolPolicy,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            separator: default_separator(),
+            policy: ToolPolicy::default(),
+        }
+    }
+}
+
+fn default_separator() -> String {
+    "__".to_string()
+}
+
+/// A downstream tool under its namespaced name
+pub struct ProxiedTool {
+    /// The tool as listed to clients
+    pub tool: McpTool,
+    pub adaptor: McpToolAdaptor,
+    /// Never [`ApprovalPolicy::Deny`], those tools are left out
+    pub policy: ApprovalPolicy,
+}
+
+/// The merged tool set of all MCP servers
+#[derive(Default)]
+pub struct ToolProxy {
+    tools: BTreeMap<String, ProxiedTool>,
+}
+
+impl ToolProxy {
+    pub fn new(config: &ProxyConfig, tools: &HashMap<String, Vec<McpToolAdaptor>>) -> Self {
+        let mut proxied = BTreeMap::new();
+
+        for (server, server_tools) in tools {
+            for adaptor in server_tools {
+                let name = &adaptor.tool().name;
+                let policy = config.policy.policy(server, name);
+                if policy == ApprovalPolicy::Deny {
+                    tracing::info!(%server, tool=%name, "Hide denied tool");
+                    continue;
+                }
+
+                let mut tool = adaptor.tool().clone();
+                tool.name = format!("{server}{}{name}", config.separator).into();
+
+                proxied.insert(
+                    tool.name.to_string(),
+                    ProxiedTool {
+                        tool,
+                        adaptor: adaptor.clone(),
+                        policy,
+                    },
+                );
+            }
+        }
+
+        Self { tools: proxied }
+    }
+
+    /// Tools sorted by namespaced name
+    pub fn tools(&self) -> impl Iterator<Item = &McpTool> {
+        self.tools.values().map(|proxied| &proxied.tool)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProxiedTool> {
+        self.tools.get(name)
+    }
+}
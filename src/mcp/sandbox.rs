@@ -0,0 +1,374 @@
+use std::{env, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Restrictions of a spawned process, none of them is applied by default
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SandboxProfile {
+    /// Working directory of the process
+    pub cwd: Option<PathBuf>,
+    /// Pass only the variables of `keep_env`, plus the ones configured for the process
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Variables kept by `clear_env`
+    #[serde(default = "default_keep_env")]
+    pub keep_env: Vec<String>,
+    /// Seconds of CPU time before the process is killed
+    pub max_cpu_secs: Option<u64>,
+    /// Bytes of address space. Runtimes reserving a large address space up
+    /// front, like node or the JVM, fail to start below several gigabytes.
+    pub max_memory: Option<u64>,
+    /// Number of open file descriptors
+    pub max_open_files: Option<u64>,
+    /// Cut the process off the network, with a network namespace or, when
+    /// namespaces are not allowed, a seccomp filter refusing IP sockets.
+    /// Linux only.
+    #[serde(default)]
+    pub no_network: bool,
+}
+
+fn default_keep_env() -> Vec<String> {
+    ["PATH", "HOME", "LANG", "TERM", "TMPDIR", "USER"]
+        .map(String::from)
+        .to_vec()
+}
+
+impl SandboxProfile {
+    /// Restrict `command`, the variables configured for the process are to be
+    /// set afterwards
+    pub fn apply(&self, command: &mut tokio::process::Command) -> anyhow::Result<()> {
+        if let Some(cwd) = &self.cwd {
+            if !cwd.is_dir() {
+                return Err(anyhow::anyhow!(
+                    "Sandbox directory {} does not exist",
+                    cwd.display()
+                ));
+            }
+            command.current_dir(cwd);
+        }
+
+        if self.clear_env {
+            command.env_clear();
+            for name in &self.keep_env {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+
+        if self.max_cpu_secs.is_none()
+            && self.max_memory.is_none()
+            && self.max_open_files.is_none()
+            && !self.no_network
+        {
+            return Ok(());
+        }
+        self.restrict(command)
+    }
+
+    #[cfg(unix)]
+    fn restrict(&self, command: &mut tokio::process::Command) -> anyhow::Result<()> {
+        #[cfg(target_os = "linux")]
+        let network = match self.no_network {
+            true => Some(linux::NoNetwork::new()),
+            false => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if self.no_network {
+            return Err(anyhow::anyhow!(
+                "`no_network` is not supported on this platform"
+            ));
+        }
+
+        let limits = [
+            (libc::RLIMIT_CPU, self.max_cpu_secs),
+            (libc::RLIMIT_AS, self.max_memory),
+            (libc::RLIMIT_NOFILE, self.max_open_files),
+        ];
+
+        // SAFETY: the closure runs in the forked child, it only makes system
+        // calls and allocates nothing
+        unsafe {
+            command.pre_exec(move || {
+                #[cfg(target_os = "linux")]
+                if let Some(network) = &network {
+                    network.apply()?;
+                }
+
+                for (resource, limit) in limits {
+                    let Some(limit) = limit else {
+                        continue;
+                    };
+                    let limit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn restrict(&self, _command: &mut tokio::process::Command) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Resource limits and `no_network` are not supported on this platform"
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{ffi::CStr, io};
+
+    /// Everything prepared before the fork, the child may not allocate
+    pub struct NoNetwork {
+        uid_map: String,
+        gid_map: String,
+        filter: Vec<libc::sock_filter>,
+    }
+
+    impl NoNetwork {
+        pub fn new() -> Self {
+            // SAFETY: both calls always succeed
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+            Self {
+                uid_map: format!("{uid} {uid} 1"),
+                gid_map: format!("{gid} {gid} 1"),
+                filter: socket_filter(),
+            }
+        }
+
+        /// Move the process to a new network namespace, only holding a
+        /// loopback device which is down. Unprivileged users need a user
+        /// namespace too, mapping them to themselves.
+        ///
+        /// # Safety
+        ///
+        /// Only called in the forked child
+        pub unsafe fn apply(&self) -> io::Result<()> {
+            let namespaced = unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) };
+            if namespaced == 0 {
+                unsafe {
+                    write(c"/proc/self/setgroups", "deny")?;
+                    write(c"/proc/self/uid_map", &self.uid_map)?;
+                    write(c"/proc/self/gid_map", &self.gid_map)?;
+                }
+                return Ok(());
+            }
+
+            unsafe { self.seccomp() }
+        }
+
+        /// Fallback when user namespaces are disabled
+        unsafe fn seccomp(&self) -> io::Result<()> {
+            // No filter for this architecture
+            if self.filter.is_empty() {
+                return Err(io::ErrorKind::Unsupported.into());
+            }
+
+            let program = libc::sock_fprog {
+                len: self.filter.len() as libc::c_ushort,
+                filter: self.filter.as_ptr() as *mut libc::sock_filter,
+            };
+            unsafe {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                    || libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &program as *const libc::sock_fprog,
+                    ) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    unsafe fn write(path: &CStr, content: &str) -> io::Result<()> {
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr().cast(), content.len());
+            libc::close(fd);
+            if written != content.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Refuse IPv4 and IPv6 sockets, and io_uring which could open them.
+    /// Local sockets are still allowed.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn socket_filter() -> Vec<libc::sock_filter> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        fn statement(code: u16, k: u32) -> libc::sock_filter {
+            jump(code, k, 0, 0)
+        }
+        fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+            libc::sock_filter { code, jt, jf, k }
+        }
+
+        // Offsets in `struct seccomp_data`
+        const NR: u32 = 0;
+        const ARCH: u32 = 4;
+        const FIRST_ARG: u32 = 16;
+        // Syscalls of the x32 ABI, which share the architecture of x86_64
+        const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+        let load = (BPF_LD | BPF_W | BPF_ABS) as u16;
+        let jump_eq = (BPF_JMP | BPF_JEQ | BPF_K) as u16;
+        let jump_ge = (BPF_JMP | BPF_JGE | BPF_K) as u16;
+        let ret = (BPF_RET | BPF_K) as u16;
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EACCES as u32 & libc::SECCOMP_RET_DATA);
+
+        vec![
+            statement(load, ARCH),
+            jump(jump_eq, AUDIT_ARCH, 1, 0),
+            statement(ret, deny),
+            statement(load, NR),
+            jump(jump_ge, X32_SYSCALL_BIT, 6, 0),
+            jump(jump_eq, libc::SYS_io_uring_setup as u32, 5, 0),
+            jump(jump_eq, libc::SYS_socket as u32, 0, 3),
+            statement(load, FIRST_ARG),
+            jump(jump_eq, libc::AF_INET as u32, 2, 0),
+            jump(jump_eq, libc::AF_INET6 as u32, 1, 0),
+            statement(ret, libc::SECCOMP_RET_ALLOW),
+            statement(ret, deny),
+        ]
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn socket_filter() -> Vec<libc::sock_filter> {
+        Vec::new()
+    }
+
+    #[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn the_filter_refuses_ip_sockets() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let network = NoNetwork::new();
+
+            let mut command = tokio::process::Command::new("bash");
+            command
+                .arg("-c")
+                .arg(format!("exec 3<>/dev/tcp/127.0.0.1/{port}"));
+            // SAFETY: only installs the filter, which is built before the fork
+            unsafe {
+                command.pre_exec(move || network.seccomp());
+            }
+            let output = command.output().await.unwrap();
+
+            assert!(!output.status.success());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("Permission denied"), "{stderr}");
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::process::Output;
+
+    use super::*;
+
+    async fn run(profile: &SandboxProfile, script: &str) -> Output {
+        let mut command = tokio::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(script)
+            .env("SANDBOX_SECRET", "hidden");
+        profile.apply(&mut command).unwrap();
+
+        command.output().await.unwrap()
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn applies_the_resource_limits() {
+        let profile = SandboxProfile {
+            max_cpu_secs: Some(7),
+            max_memory: Some(512 << 20),
+            max_open_files: Some(32),
+            ..Default::default()
+        };
+
+        let output = run(&profile, "ulimit -n; ulimit -t; ulimit -v").await;
+
+        assert!(output.status.success());
+        assert_eq!(stdout(&output), "32\n7\n524288");
+    }
+
+    #[tokio::test]
+    async fn clears_the_environment() {
+        let output = run(&SandboxProfile::default(), "echo ${SANDBOX_SECRET-unset}").await;
+        assert_eq!(stdout(&output), "hidden");
+
+        let profile = SandboxProfile {
+            clear_env: true,
+            ..Default::default()
+        };
+        let output = run(&profile, "echo ${SANDBOX_SECRET-unset}; echo ${PATH:+path}").await;
+        assert_eq!(stdout(&output), "unset\npath");
+    }
+
+    #[tokio::test]
+    async fn runs_in_the_directory() {
+        let dir = env::temp_dir();
+        let profile = SandboxProfile {
+            cwd: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let output = run(&profile, "pwd -P").await;
+
+        assert_eq!(
+            stdout(&output),
+            dir.canonicalize().unwrap().to_string_lossy()
+        );
+    }
+
+    #[tokio::test]
+    async fn cuts_the_network() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // `sh` may have no `/dev/tcp`
+        let connect = format!("bash -c 'exec 3<>/dev/tcp/127.0.0.1/{port}' 2>/dev/null");
+
+        let output = run(&SandboxProfile::default(), &connect).await;
+        assert!(output.status.success(), "the connection fails unsandboxed");
+
+        let profile = SandboxProfile {
+            no_network: true,
+            ..Default::default()
+        };
+        let output = run(&profile, &connect).await;
+        assert!(!output.status.success());
+    }
+}
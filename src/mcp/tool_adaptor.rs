@@ -1,15 +1,82 @@
-use std::vec;
+use std::{sync::Arc, vec};
 
 use rig::tool::{ToolDyn as RigTool, ToolEmbeddingDyn, ToolSet};
 use rmcp::{
-    model::{CallToolRequestParam, CallToolResult, Tool as McpTool},
+    ServiceError,
+    model::{CallToolRequestParam, CallToolResult, Content, JsonObject, Tool as McpTool},
     serde_json,
     service::ServerSink,
 };
 
+/// Where the calls of a tool go
+#[derive(Clone)]
+enum Backend {
+    /// The MCP server listing the tool
+    Server(ServerSink),
+    /// A rig tool run by whisper itself
+    Native(Arc<dyn RigTool>),
+}
+
+#[derive(Clone)]
 pub struct McpToolAdaptor {
     tool: McpTool,
-    server: ServerSink,
+    backend: Backend,
+}
+
+impl McpToolAdaptor {
+    /// Serve a rig tool like the tools of MCP servers
+    pub async fn native(tool: impl RigTool + 'static) -> Self {
+        let definition = tool.definition(String::new()).await;
+        let input_schema = match definition.parameters {
+            serde_json::Value::Object(schema) => schema,
+            _ => JsonObject::new(),
+        };
+
+        Self {
+            tool: McpTool::new(definition.name, definition.description, input_schema),
+            backend: Backend::Native(Arc::new(tool)),
+        }
+    }
+
+    /// The tool as listed by its server
+    pub fn tool(&self) -> &McpTool {
+        &self.tool
+    }
+
+    /// Call the tool on its server and keep the result as is
+    pub async fn call_mcp(
+        &self,
+        arguments: Option<JsonObject>,
+    ) -> Result<CallToolResult, ServiceError> {
+        let server = match &self.backend {
+            Backend::Server(server) => server,
+            Backend::Native(tool) => {
+                let arguments = serde_json::Value::Object(arguments.unwrap_or_default());
+                return Ok(match tool.call(arguments.to_string()).await {
+                    Ok(output) => CallToolResult::success(vec![Content::text(plain_text(output))]),
+                    Err(rig::tool::ToolError::ToolCallError(e)) => {
+                        CallToolResult::error(vec![Content::text(e.to_string())])
+                    }
+                    Err(e) => CallToolResult::error(vec![Content::text(e.to_string())]),
+                });
+            }
+        };
+
+        server
+            .call_tool(CallToolRequestParam {
+                name: self.tool.name.clone(),
+                arguments,
+            })
+            .await
+    }
+}
+
+/// Rig tools return their output as JSON, which quotes plain text
+fn plain_text(output: String) -> String {
+    match serde_json::from_str(&output) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => output,
+    }
 }
 
 impl RigTool for McpToolAdaptor {
@@ -39,7 +106,10 @@ impl RigTool for McpToolAdaptor {
         args: String,
     ) -> std::pin::Pin<Box<dyn Future<Output = Result<String, rig::tool::ToolError>> + Send + '_>>
     {
-        let server = self.server.clone();
+        let server = match &self.backend {
+            Backend::Server(server) => server.clone(),
+            Backend::Native(tool) => return tool.call(args),
+        };
         Box::pin(async move {
             let call_mcp_tool_result = server
                 .call_tool(CallToolRequestParam {
@@ -77,6 +147,18 @@ pub fn convert_mcp_call_tool_result_to_string(result: CallToolResult) -> String
     serde_json::to_string(&result).unwrap()
 }
 
+pub async fn get_tools(server: ServerSink) -> anyhow::Result<Vec<McpToolAdaptor>> {
+    let tools = server.list_all_tools().await?;
+
+    Ok(tools
+        .into_iter()
+        .map(|tool| McpToolAdaptor {
+            tool,
+            backend: Backend::Server(server.clone()),
+        })
+        .collect())
+}
+
 pub async fn get_tool_set(server: ServerSink) -> anyhow::Result<ToolSet> {
     let tools = server.list_all_tools().await?;
     let mut tool_builder = ToolSet::builder();
@@ -85,7 +167,7 @@ pub async fn get_tool_set(server: ServerSink) -> anyhow::Result<ToolSet> {
         tracing::info!("get tool: {}", tool.name);
         let adaptor = McpToolAdaptor {
             tool: tool.clone(),
-            server: server.clone(),
+            backend: Backend::Server(server.clone()),
         };
         tool_builder = tool_builder.dynamic_tool(adaptor);
     }
@@ -3,6 +3,8 @@ use std::{collections::HashMap, process::Stdio};
 use rmcp::{RoleClient, ServiceExt, service::RunningService, transport::ConfigureCommandExt};
 use serde::{self, Deserialize, Serialize};
 
+use super::sandbox::SandboxProfile;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "protocol", rename_all = "lowercase")]
 pub enum TransportConfig {
@@ -19,6 +21,9 @@ pub enum TransportConfig {
         command: String,
         args: Vec<String>,
         envs: HashMap<String, String>,
+        /// Restrictions of the server process, none if missing
+        #[serde(default)]
+        sandbox: Option<SandboxProfile>,
     },
 }
 
@@ -40,12 +45,16 @@ pub async fn start(config: TransportConfig) -> anyhow::Result<RunningService<Rol
             command,
             args,
             envs,
+            sandbox,
         } => {
-            let transport = rmcp::transport::TokioChildProcess::new(
-                tokio::process::Command::new(command).configure(|cmd| {
-                    cmd.args(args).envs(envs).stderr(Stdio::null());
-                }),
-            )?;
+            let mut command = tokio::process::Command::new(command);
+            if let Some(sandbox) = sandbox {
+                sandbox.apply(&mut command)?;
+            }
+
+            let transport = rmcp::transport::TokioChildProcess::new(command.configure(|cmd| {
+                cmd.args(args).envs(envs).stderr(Stdio::null());
+            }))?;
 
             ().serve(transport).await?
         }
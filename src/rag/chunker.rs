@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of characters of a chunk
+const CHUNK_CHARS: usize = 1200;
+/// Number of lines shared by two neighbouring chunks
+const OVERLAP_LINES: usize = 3;
+/// Files larger than this are skipped
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Extensions of the files that could be ingested
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rst", "org", "rs", "py", "js", "jsx", "ts", "tsx", "go", "c", "h",
+    "cc", "cpp", "hpp", "java", "kt", "cs", "swift", "zig", "lua", "rb", "php", "sh", "bash",
+    "zsh", "fish", "toml", "yaml", "yml", "json", "html", "css", "scss", "sql", "nix",
+];
+
+/// Directories never walked into
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "__pycache__", "venv"];
+
+/// A piece of a local file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    /// Path of the source file
+    pub source: String,
+    /// First line of the chunk, starting from 1
+    pub start_line: usize,
+    /// Last line of the chunk, inclusive
+    pub end_line: usize,
+    pub text: String,
+}
+
+impl DocumentChunk {
+    /// Unique id, also used by the model to cite the chunk
+    pub fn id(&self) -> String {
+        format!("{}:{}-{}", self.source, self.start_line, self.end_line)
+    }
+}
+
+/// Collect all ingestible files under `path`
+pub fn collect_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(files);
+    }
+
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        // The rest of the tree is still worth ingesting
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == path => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!("Skip unreadable directory {}: {e}", dir.display());
+                continue;
+            }
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() && is_text_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn is_text_file(path: &Path) -> bool {
+    let known_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    let small_enough = path
+        .metadata()
+        .is_ok_and(|meta| meta.len() <= MAX_FILE_BYTES);
+
+    known_extension && small_enough
+}
+
+/// Read a file and split it into chunks
+pub fn chunk_file(path: &Path) -> anyhow::Result<Vec<DocumentChunk>> {
+    let content = std::fs::read_to_string(path)?;
+    let is_markdown = path
+        .extension()
+        .is_some_and(|e| e == "md" || e == "markdown");
+
+    Ok(chunk_text(&path.to_string_lossy(), &content, is_markdown))
+}
+
+/// Split text into chunks of whole lines.
+///
+/// Neighbouring chunks share a few lines so that a sentence is never lost
+/// at the border. For markdown, a heading always starts a new chunk.
+pub fn chunk_text(source: &str, content: &str, is_markdown: bool) -> Vec<DocumentChunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut chunks = Vec::new();
+
+    let mut start = 0;
+    let mut size = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let is_heading = is_markdown && line.starts_with('#');
+        let full = size + line.len() > CHUNK_CHARS;
+
+        if i > start && (full || is_heading) {
+            push_chunk(&mut chunks, source, &lines, start, i);
+
+            // Headings open a fresh section, no need to overlap
+            start = if is_heading {
+                i
+            } else {
+                i.saturating_sub(OVERLAP_LINES).max(start + 1)
+            };
+            size = lines_size(&lines[start..i]);
+
+            // Keep the overlap small, or long lines would be repeated in every chunk
+            while start < i && size > CHUNK_CHARS / 4 {
+                size -= lines[start].len() + 1;
+                start += 1;
+            }
+        }
+
+        size += line.len() + 1;
+    }
+
+    if start < lines.len() {
+        push_chunk(&mut chunks, source, &lines, start, lines.len());
+    }
+
+    chunks
+}
+
+fn lines_size(lines: &[&str]) -> usize {
+    lines.iter().map(|l| l.len() + 1).sum()
+}
+
+fn push_chunk(
+    chunks: &mut Vec<DocumentChunk>,
+    source: &str,
+    lines: &[&str],
+    start: usize,
+    end: usize,
+) {
+    let text = lines[start..end].join("\n");
+    if text.trim().is_empty() {
+        return;
+    }
+
+    chunks.push(DocumentChunk {
+        source: source.to_string(),
+        start_line: start + 1,
+        end_line: end,
+        text,
+    });
+}
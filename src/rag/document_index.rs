@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use rig::vector_store::{VectorSearchRequest, VectorStoreError, VectorStoreIndex};
+use serde::Deserialize;
+
+use super::{chunker, store::VectorRecord, vector_index::VectorIndex};
+
+/// Knowledge base built from local files.
+///
+/// The index is shared: cloning it is cheap, and chunks ingested through
+/// one clone are visible to every agent holding another one.
+#[derive(Clone)]
+pub struct DocumentIndex {
+    index: VectorIndex,
+}
+
+impl DocumentIndex {
+    pub fn new(index: VectorIndex) -> Self {
+        Self { index }
+    }
+
+    /// Chunk and embed every file under `path`, then upsert them in the store.
+    ///
+    /// Chunks previously ingested from the same files are replaced.
+    /// Return the number of new chunks.
+    pub async fn ingest(&self, path: &Path) -> anyhow::Result<usize> {
+        let mut count = 0;
+
+        for file in chunker::collect_files(path)? {
+            let chunks = match chunker::chunk_file(&file) {
+                Ok(chunks) => chunks,
+                // Binary or non UTF-8 file, skip it
+                Err(e) => {
+                    tracing::warn!(file=%file.display(), error=%e, "Skip file");
+                    continue;
+                }
+            };
+
+            let texts = chunks.iter().map(|c| c.text.clone()).collect();
+            let embeddings = self.index.embed(texts).await?;
+
+            let mut records = Vec::with_capacity(chunks.len());
+            for (chunk, embedding) in chunks.into_iter().zip(embeddings) {
+                records.push(VectorRecord {
+                    id: chunk.id(),
+                    group: chunk.source.clone(),
+                    document: serde_json::to_value(chunk)?,
+                    embedding,
+                    model: self.index.model_name().to_string(),
+                });
+            }
+
+            count += records.len();
+
+            let store = self.index.store();
+            store.delete_group(&file.to_string_lossy()).await?;
+            store.upsert(records).await?;
+        }
+
+        Ok(count)
+    }
+}
+
+impl VectorStoreIndex for DocumentIndex {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        req: VectorSearchRequest,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.index.top_n(req).await
+    }
+
+    async fn top_n_ids(
+        &self,
+        req: VectorSearchRequest,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        self.index.top_n_ids(req).await
+    }
+}
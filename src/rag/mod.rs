@@ -0,0 +1,4 @@
+pub mod chunker;
+pub mod document_index;
+pub mod store;
+pub mod vector_index;
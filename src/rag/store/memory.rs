@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use super::{VectorRecord, VectorStore};
+
+/// Store living only as long as the process
+#[derive(Default)]
+pub struct MemoryStore {
+    records: RwLock<BTreeMap<String, VectorRecord>>,
+}
+
+#[async_trait]
+impl VectorStore for MemoryStore {
+    async fn upsert(&self, records: Vec<VectorRecord>) -> anyhow::Result<()> {
+        let mut map = self.records.write().await;
+        for record in records {
+            map.insert(record.id.clone(), record);
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, ids: &[String]) -> anyhow::Result<()> {
+        let mut map = self.records.write().await;
+        for id in ids {
+            map.remove(id);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_group(&self, group: &str) -> anyhow::Result<()> {
+        self.records.write().await.retain(|_, r| r.group != group);
+
+        Ok(())
+    }
+
+    async fn records(&self) -> anyhow::Result<Vec<VectorRecord>> {
+        Ok(self.records.read().await.values().cloned().collect())
+    }
+}
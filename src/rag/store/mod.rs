@@ -0,0 +1,211 @@
+pub mod memory;
+pub mod sqlite;
+
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// A document with its embedding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorRecord {
+    /// Unique id inside the collection
+    pub id: String,
+    /// Records of the same group are replaced together, e.g. chunks of a file
+    pub group: String,
+    pub document: serde_json::Value,
+    pub embedding: Vec<f64>,
+    /// Name of the embedding model which built `embedding`
+    pub model: String,
+}
+
+/// Backend persisting the embeddings of one collection
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Insert the records, or replace those with the same id
+    async fn upsert(&self, records: Vec<VectorRecord>) -> anyhow::Result<()>;
+
+    /// Delete the records by id
+    async fn delete(&self, ids: &[String]) -> anyhow::Result<()>;
+
+    /// Delete all records of a group
+    async fn delete_group(&self, group: &str) -> anyhow::Result<()>;
+
+    /// Get all records
+    async fn records(&self) -> anyhow::Result<Vec<VectorRecord>>;
+
+    /// Return the `samples` records most similar to `query`, embedded by
+    /// `model`, best first
+    async fn search(
+        &self,
+        query: &[f64],
+        model: &str,
+        samples: usize,
+        threshold: Option<f64>,
+    ) -> anyhow::Result<Vec<(f64, VectorRecord)>> {
+        Ok(rank(
+            self.records().await?,
+            query,
+            model,
+            samples,
+            threshold,
+        ))
+    }
+}
+
+/// Which backend stores the vectors
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum VectorStoreConfig {
+    /// Rebuilt on every launch
+    Memory,
+    /// Persisted in a SQLite database
+    Sqlite {
+        #[serde(default = "default_sqlite_path")]
+        path: PathBuf,
+    },
+}
+
+impl Default for VectorStoreConfig {
+    fn default() -> Self {
+        Self::Sqlite {
+            path: default_sqlite_path(),
+        }
+    }
+}
+
+fn default_sqlite_path() -> PathBuf {
+    PathBuf::from(".whisper/vectors.db")
+}
+
+/// Open the store of a collection
+pub async fn open(
+    config: &VectorStoreConfig,
+    collection: &str,
+) -> anyhow::Result<Arc<dyn VectorStore>> {
+    let store: Arc<dyn VectorStore> = match config {
+        VectorStoreConfig::Memory => Arc::new(memory::MemoryStore::default()),
+        VectorStoreConfig::Sqlite { path } => {
+            Arc::new(sqlite::SqliteStore::open(path.clone(), collection).await?)
+        }
+    };
+
+    Ok(store)
+}
+
+/// Brute force ranking by cosine similarity. Records embedded by another
+/// model than the query are not comparable, they are skipped with a warning
+pub(crate) fn rank(
+    records: Vec<VectorRecord>,
+    query: &[f64],
+    model: &str,
+    samples: usize,
+    threshold: Option<f64>,
+) -> Vec<(f64, VectorRecord)> {
+    let total = records.len();
+    let (records, foreign): (Vec<VectorRecord>, Vec<VectorRecord>) = records
+        .into_iter()
+        .partition(|r| r.model == model && r.embedding.len() == query.len());
+    if !foreign.is_empty() {
+        tracing::warn!(
+            "Skipped {} of {total} records not embedded by `{model}`, ingest them again",
+            foreign.len()
+        );
+    }
+
+    let mut ranked: Vec<(f64, VectorRecord)> = records
+        .into_iter()
+        .map(|r| (cosine_similarity(query, &r.embedding), r))
+        .filter(|(score, _)| threshold.is_none_or(|t| *score >= t))
+        .collect();
+
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranked.truncate(samples);
+
+    ranked
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, embedding: Vec<f64>, model: &str) -> VectorRecord {
+        VectorRecord {
+            id: id.to_string(),
+            group: "docs".to_string(),
+            document: serde_json::Value::Null,
+            embedding,
+            model: model.to_string(),
+        }
+    }
+
+    fn ids(ranked: &[(f64, VectorRecord)]) -> Vec<&str> {
+        ranked.iter().map(|(_, r)| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_by_cosine_similarity() {
+        let records = vec![
+            record("far", vec![0.0, 1.0], "m"),
+            record("same", vec![2.0, 0.0], "m"),
+            record("close", vec![1.0, 1.0], "m"),
+        ];
+
+        let ranked = rank(records, &[1.0, 0.0], "m", 10, None);
+
+        assert_eq!(ids(&ranked), ["same", "close", "far"]);
+        assert!((ranked[0].0 - 1.0).abs() < 1e-9);
+        assert!(ranked[2].0.abs() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_samples_above_the_threshold() {
+        let records = vec![
+            record("far", vec![0.0, 1.0], "m"),
+            record("same", vec![1.0, 0.0], "m"),
+            record("close", vec![1.0, 1.0], "m"),
+        ];
+
+        assert_eq!(
+            ids(&rank(records.clone(), &[1.0, 0.0], "m", 1, None)),
+            ["same"]
+        );
+        assert_eq!(
+            ids(&rank(records, &[1.0, 0.0], "m", 10, Some(0.5))),
+            ["same", "close"]
+        );
+    }
+
+    #[test]
+    fn skips_records_of_another_model() {
+        let records = vec![
+            record("ours", vec![1.0, 0.0], "m"),
+            record("migrated", vec![1.0, 0.0], ""),
+            record("other", vec![1.0, 0.0], "n"),
+            record("resized", vec![1.0, 0.0, 0.0], "m"),
+        ];
+
+        assert_eq!(ids(&rank(records, &[1.0, 0.0], "m", 10, None)), ["ours"]);
+    }
+
+    #[test]
+    fn zero_vectors_are_not_similar() {
+        let records = vec![record("zero", vec![0.0, 0.0], "m")];
+
+        let ranked = rank(records, &[1.0, 0.0], "m", 10, None);
+
+        assert_eq!(ranked[0].0, 0.0);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use rusqlite::{Connection, params};
+
+use super::{VectorRecord, VectorStore};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS vectors (
+    collection TEXT NOT NULL,
+    id         TEXT NOT NULL,
+    grp        TEXT NOT NULL,
+    document   TEXT NOT NULL,
+    embedding  BLOB NOT NULL,
+    model      TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (collection, id)
+);
+CREATE INDEX IF NOT EXISTS vectors_group ON vectors (collection, grp);
+";
+
+/// Databases created before the model was stored. Their records are from an
+/// unknown model: tools get embedded again, documents are skipped by searches
+/// until ingested again
+const ADD_MODEL: &str = "ALTER TABLE vectors ADD COLUMN model TEXT NOT NULL DEFAULT ''";
+
+/// Store persisted in a `vectors` table, shared by all collections
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    collection: String,
+}
+
+impl SqliteStore {
+    pub async fn open(path: PathBuf, collection: &str) -> anyhow::Result<Self> {
+        let conn = tokio::task::spawn_blocking(move || -> anyhow::Result<Connection> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let conn = Connection::open(&path)?;
+            conn.execute_batch(SCHEMA)?;
+            let has_model = conn
+                .prepare("SELECT 1 FROM pragma_table_info('vectors') WHERE name = 'model'")?
+                .exists([])?;
+            if !has_model {
+                conn.execute(ADD_MODEL, [])?;
+            }
+
+            Ok(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            collection: collection.to_string(),
+        })
+    }
+
+    /// Run a blocking closure on the connection
+    async fn with_conn<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection, &str) -> anyhow::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let collection = self.collection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("SQLite connection poisoned"))?;
+            f(&mut conn, &collection)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl VectorStore for SqliteStore {
+    async fn upsert(&self, records: Vec<VectorRecord>) -> anyhow::Result<()> {
+        self.with_conn(move |conn, collection| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO vectors (collection, id, grp, document, embedding, model)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for record in &records {
+                    stmt.execute(params![
+                        collection,
+                        record.id,
+                        record.group,
+                        serde_json::to_string(&record.document)?,
+                        encode_embedding(&record.embedding),
+                        record.model,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete(&self, ids: &[String]) -> anyhow::Result<()> {
+        let ids = ids.to_vec();
+        self.with_conn(move |conn, collection| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt =
+                    tx.prepare("DELETE FROM vectors WHERE collection = ?1 AND id = ?2")?;
+                for id in &ids {
+                    stmt.execute(params![collection, id])?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_group(&self, group: &str) -> anyhow::Result<()> {
+        let group = group.to_string();
+        self.with_conn(move |conn, collection| {
+            conn.execute(
+                "DELETE FROM vectors WHERE collection = ?1 AND grp = ?2",
+                params![collection, group],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn records(&self) -> anyhow::Result<Vec<VectorRecord>> {
+        self.with_conn(|conn, collection| {
+            let mut stmt = conn.prepare(
+                "SELECT id, grp, document, embedding, model FROM vectors WHERE collection = ?1",
+            )?;
+            let rows = stmt.query_map(params![collection], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })?;
+
+            let mut records = Vec::new();
+            for row in rows {
+                let (id, group, document, embedding, model) = row?;
+                records.push(VectorRecord {
+                    id,
+                    group,
+                    document: serde_json::from_str(&document)?,
+                    embedding: decode_embedding(&embedding),
+                    model,
+                });
+            }
+
+            Ok(records)
+        })
+        .await
+    }
+}
+
+fn encode_embedding(embedding: &[f64]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode_embedding(bytes: &[u8]) -> Vec<f64> {
+    bytes
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().expect("chunk of 8 bytes")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Database path unique to the test, removed first
+    fn db_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("whisper-{name}-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn embeddings_round_trip() {
+        let embedding = vec![0.0, -1.5, f64::MAX, f64::MIN_POSITIVE, 1.0 / 3.0];
+
+        let bytes = encode_embedding(&embedding);
+
+        assert_eq!(bytes.len(), 8 * embedding.len());
+        assert_eq!(decode_embedding(&bytes), embedding);
+        assert!(decode_embedding(&[]).is_empty());
+    }
+
+    #[test]
+    fn decoding_ignores_a_partial_value() {
+        let mut bytes = encode_embedding(&[2.0]);
+        bytes.extend([1, 2, 3]);
+
+        assert_eq!(decode_embedding(&bytes), [2.0]);
+    }
+
+    #[tokio::test]
+    async fn stores_records_by_collection() {
+        let path = db_path("collections");
+        let docs = SqliteStore::open(path.clone(), "docs").await.unwrap();
+        let tools = SqliteStore::open(path.clone(), "tools").await.unwrap();
+
+        let record = VectorRecord {
+            id: "a.md#0".to_string(),
+            group: "a.md".to_string(),
+            document: serde_json::json!({ "text": "hello" }),
+            embedding: vec![0.25, 0.5],
+            model: "local".to_string(),
+        };
+        docs.upsert(vec![record]).await.unwrap();
+
+        let records = docs.records().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].document["text"], "hello");
+        assert_eq!(records[0].embedding, [0.25, 0.5]);
+        assert_eq!(records[0].model, "local");
+        assert!(tools.records().await.unwrap().is_empty());
+
+        docs.delete_group("a.md").await.unwrap();
+        assert!(docs.records().await.unwrap().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn adds_the_model_column_to_old_databases() {
+        let path = db_path("migration");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE vectors (
+                    collection TEXT NOT NULL,
+                    id         TEXT NOT NULL,
+                    grp        TEXT NOT NULL,
+                    document   TEXT NOT NULL,
+                    embedding  BLOB NOT NULL,
+                    PRIMARY KEY (collection, id)
+                );",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO vectors VALUES ('docs', 'old', 'a.md', 'null', ?1)",
+                params![encode_embedding(&[1.0, 0.0])],
+            )
+            .unwrap();
+        }
+
+        let store = SqliteStore::open(path.clone(), "docs").await.unwrap();
+
+        let records = store.records().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "old");
+        assert_eq!(records[0].model, "");
+        assert!(
+            store
+                .search(&[1.0, 0.0], "local", 10, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Opening again finds the column
+        drop(store);
+        SqliteStore::open(path.clone(), "docs").await.unwrap();
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
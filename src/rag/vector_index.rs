@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use rig::{
+    embeddings::{embedding::EmbeddingModelDyn, tool::ToolSchema},
+    vector_store::{VectorSearchRequest, VectorStoreError, VectorStoreIndex},
+};
+use serde::Deserialize;
+
+use super::store::{VectorRecord, VectorStore, rank};
+
+/// Group of the records describing tools
+const TOOL_GROUP: &str = "tools";
+
+/// Search a [`VectorStore`] with an embedding model, usable as rig's dynamic
+/// context or dynamic tools.
+#[derive(Clone)]
+pub struct VectorIndex {
+    /// Stored with the records, which are embedded again when it changes
+    model_name: String,
+    model: Arc<dyn EmbeddingModelDyn>,
+    store: Arc<dyn VectorStore>,
+    /// Ids the search is restricted to, all records if `None`
+    ids: Option<Arc<HashSet<String>>>,
+}
+
+impl VectorIndex {
+    pub fn new(
+        model_name: impl Into<String>,
+        model: Arc<dyn EmbeddingModelDyn>,
+        store: Arc<dyn VectorStore>,
+    ) -> Self {
+        Self {
+            model_name: model_name.into(),
+            model,
+            store,
+            ids: None,
+        }
+    }
+
+    /// Only find the records of `ids`
+    pub fn only(mut self, ids: HashSet<String>) -> Self {
+        self.ids = Some(Arc::new(ids));
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn VectorStore> {
+        &self.store
+    }
+
+    /// Name of the embedding model
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Embed the texts in batches the model could accept
+    pub async fn embed(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f64>>> {
+        let mut vecs = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(self.model.max_documents().max(1)) {
+            let embeddings = self.model.embed_texts(batch.to_vec()).await?;
+            vecs.extend(embeddings.into_iter().map(|e| e.vec));
+        }
+
+        Ok(vecs)
+    }
+
+    /// Make the store hold exactly the given tools.
+    ///
+    /// Only new or changed tools are embedded, so a persistent store makes
+    /// the startup cheap.
+    pub async fn sync_tools(&self, schemas: Vec<ToolSchema>) -> anyhow::Result<()> {
+        let stored: HashMap<String, VectorRecord> = self
+            .store
+            .records()
+            .await?
+            .into_iter()
+            .map(|r| (r.id.clone(), r))
+            .collect();
+
+        let stale: Vec<String> = stored
+            .keys()
+            .filter(|id| !schemas.iter().any(|s| &s.name == *id))
+            .cloned()
+            .collect();
+
+        let changed: Vec<ToolSchema> = schemas
+            .into_iter()
+            .filter(|s| {
+                stored.get(&s.name).is_none_or(|r| {
+                    r.document != s.context
+                        || r.model != self.model_name
+                        || r.embedding.len() != self.model.ndims()
+                })
+            })
+            .collect();
+
+        tracing::info!(
+            "Sync tool index: {} changed, {} stale",
+            changed.len(),
+            stale.len()
+        );
+
+        if !stale.is_empty() {
+            self.store.delete(&stale).await?;
+        }
+
+        let texts = changed
+            .iter()
+            .map(|s| s.embedding_docs.join("\n"))
+            .collect();
+        let embeddings = self.embed(texts).await?;
+        let records = changed
+            .into_iter()
+            .zip(embeddings)
+            .map(|(schema, embedding)| VectorRecord {
+                id: schema.name,
+                group: TOOL_GROUP.to_string(),
+                document: schema.context,
+                embedding,
+                model: self.model_name.clone(),
+            })
+            .collect();
+
+        self.store.upsert(records).await
+    }
+
+    async fn search(
+        &self,
+        req: &VectorSearchRequest,
+    ) -> Result<Vec<(f64, VectorRecord)>, VectorStoreError> {
+        let query = self.model.embed_text(req.query()).await?;
+        let samples = req.samples() as usize;
+
+        let Some(ids) = &self.ids else {
+            return self
+                .store
+                .search(&query.vec, &self.model_name, samples, req.threshold())
+                .await
+                .map_err(|e| VectorStoreError::DatastoreError(e.into()));
+        };
+
+        let records = self
+            .store
+            .records()
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(e.into()))?
+            .into_iter()
+            .filter(|r| ids.contains(&r.id))
+            .collect();
+
+        Ok(rank(
+            records,
+            &query.vec,
+            &self.model_name,
+            samples,
+            req.threshold(),
+        ))
+    }
+}
+
+impl VectorStoreIndex for VectorIndex {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        req: VectorSearchRequest,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.search(&req)
+            .await?
+            .into_iter()
+            .map(|(score, record)| Ok((score, record.id, serde_json::from_value(record.document)?)))
+            .collect()
+    }
+
+    async fn top_n_ids(
+        &self,
+        req: VectorSearchRequest,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        Ok(self
+            .search(&req)
+            .await?
+            .into_iter()
+            .map(|(score, record)| (score, record.id))
+            .collect())
+    }
+}
@@ -0,0 +1,249 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use rmcp::{
+    ErrorData as McpError, RoleServer, ServerHandler, ServiceError, ServiceExt,
+    model::{
+        CallToolRequestParam, CallToolResult, Content, CreateElicitationRequestParam,
+        ElicitationAction, Implementation, ListToolsResult, PaginatedRequestParam,
+        ServerCapabilities, ServerInfo, Tool,
+    },
+    service::{Peer, RequestContext},
+    transport::streamable_http_server::{
+        StreamableHttpService, session::local::LocalSessionManager,
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    agent::{bootstrap::Bootstrap, oneshot::CaptureSink, session::AgentProvider},
+    mcp::{policy::ApprovalPolicy, proxy::ToolProxy},
+    server::spawn_ask,
+};
+
+const ASK_TOOL: &str = "ask";
+
+/// Arguments of the `ask` tool
+#[derive(Debug, Deserialize)]
+struct AskArgs {
+    prompt: String,
+    /// Agent profile, the default one if missing
+    agent: Option<String>,
+}
+
+/// MCP server exposing the agent as the `ask` tool.
+///
+/// With a [`ToolProxy`], the merged tools of the MCP servers are listed too and
+/// their calls are forwarded after the approval policy is applied.
+#[derive(Clone)]
+pub struct WhisperMcpServer {
+    bootstrap: Arc<Bootstrap>,
+    proxy: Arc<ToolProxy>,
+    serve_ask: bool,
+}
+
+impl WhisperMcpServer {
+    pub fn new(bootstrap: Arc<Bootstrap>) -> Self {
+        Self {
+            bootstrap,
+            proxy: Arc::new(ToolProxy::default()),
+            serve_ask: true,
+        }
+    }
+
+    /// Serve the proxied tools too
+    pub fn proxy(mut self, proxy: ToolProxy) -> Self {
+        self.proxy = Arc::new(proxy);
+        self
+    }
+
+    /// Serve only the proxied tools, without the agent
+    pub fn proxy_only(mut self) -> Self {
+        self.serve_ask = false;
+        self
+    }
+
+    fn ask_tool(&self) -> Tool {
+        let agents = self.bootstrap.factory.names();
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "prompt": {
+                    "type": "string",
+                    "description": "The question or task for the agent",
+                },
+                "agent": {
+                    "type": "string",
+                    "description": "Agent profile answering the prompt",
+                    "enum": agents,
+                },
+            },
+            "required": ["prompt"],
+        });
+        let schema = match schema {
+            serde_json::Value::Object(schema) => schema,
+            _ => unreachable!("schema is an object"),
+        };
+
+        Tool::new(
+            ASK_TOOL,
+            "Ask the whisper agent, it could use its own tools and knowledge base",
+            Arc::new(schema),
+        )
+    }
+
+    async fn ask(&self, arguments: serde_json::Value) -> Result<CallToolResult, McpError> {
+        let args: AskArgs = serde_json::from_value(arguments)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+
+        let session = self
+            .bootstrap
+            .session(args.agent.as_deref())
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+
+        let (_, sink) = spawn_ask(session, args.prompt, CaptureSink::new())
+            .await
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        let report = sink.into_report();
+
+        if report.errors.is_empty() {
+            Ok(CallToolResult::success(vec![Content::text(
+                report.answer.trim(),
+            )]))
+        } else {
+            Ok(CallToolResult::error(vec![Content::text(
+                report.errors.join("\n"),
+            )]))
+        }
+    }
+}
+
+impl ServerHandler for WhisperMcpServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                ..Implementation::from_build_env()
+            },
+            instructions: self
+                .serve_ask
+                .then(|| "Use `ask` to delegate a question to the whisper agent".into()),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let mut tools = Vec::new();
+        if self.serve_ask {
+            tools.push(self.ask_tool());
+        }
+        tools.extend(self.proxy.tools().cloned());
+
+        Ok(ListToolsResult {
+            tools,
+            next_cursor: None,
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.serve_ask && request.name == ASK_TOOL {
+            let arguments = serde_json::Value::Object(request.arguments.unwrap_or_default());
+            return self.ask(arguments).await;
+        }
+
+        let Some(proxied) = self.proxy.get(request.name.as_ref()) else {
+            return Err(McpError::invalid_params(
+                format!("Unknown tool `{}`", request.name),
+                None,
+            ));
+        };
+
+        if proxied.policy == ApprovalPolicy::Ask {
+            let arguments =
+                serde_json::Value::Object(request.arguments.clone().unwrap_or_default());
+            if let Err(reason) = approve(&context.peer, &request.name, &arguments).await {
+                return Ok(CallToolResult::error(vec![Content::text(reason)]));
+            }
+        }
+
+        proxied
+            .adaptor
+            .call_mcp(request.arguments)
+            .await
+            .map_err(|e| match e {
+                ServiceError::McpError(e) => e,
+                e => McpError::internal_error(e.to_string(), None),
+            })
+    }
+}
+
+/// Ask the user of the client to approve a tool call, the reason is returned if not
+async fn approve(
+    peer: &Peer<RoleServer>,
+    name: &str,
+    arguments: &serde_json::Value,
+) -> Result<(), String> {
+    let supported = peer
+        .peer_info()
+        .is_some_and(|info| info.capabilities.elicitation.is_some());
+    if !supported {
+        return Err(format!(
+            "Tool `{name}` needs approval, but the client could not ask for it"
+        ));
+    }
+
+    let result = peer
+        .create_elicitation(CreateElicitationRequestParam {
+            message: format!("Allow calling `{name}` with arguments {arguments}?"),
+            requested_schema: serde_json::Map::from_iter([
+                ("type".to_string(), "object".into()),
+                ("properties".to_string(), serde_json::json!({})),
+            ]),
+        })
+        .await
+        .map_err(|e| format!("Failed to ask approval for `{name}`: {e}"))?;
+
+    match result.action {
+        ElicitationAction::Accept => Ok(()),
+        _ => Err(format!("Call of `{name}` was not approved")),
+    }
+}
+
+/// Serve MCP over stdin and stdout until the client disconnects
+pub async fn serve_stdio(server: WhisperMcpServer) -> anyhow::Result<()> {
+    tracing::info!("Serve MCP over stdio");
+
+    server
+        .serve(rmcp::transport::stdio())
+        .await?
+        .waiting()
+        .await?;
+
+    Ok(())
+}
+
+/// Serve MCP over streamable HTTP at `/mcp`
+pub async fn serve_http(server: WhisperMcpServer, addr: SocketAddr) -> anyhow::Result<()> {
+    let service = StreamableHttpService::new(
+        move || Ok(server.clone()),
+        LocalSessionManager::default().into(),
+        Default::default(),
+    );
+    let router = axum::Router::new().nest_service("/mcp", service);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Serve MCP over streamable HTTP on {addr}/mcp");
+
+    axum::serve(listener, router).await?;
+
+    Ok(())
+}
@@ -0,0 +1,573 @@
+pub mod mcp;
+pub mod openai;
+pub mod sink;
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use futures::Stream;
+use rig::{client::completion::CompletionModelHandle, completion::Message};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot,
+    },
+    task::LocalSet,
+};
+
+use crate::agent::{
+    bootstrap::Bootstrap,
+    session::{AgentImpl, AgentProvider, ChatSession, ResponseSink, Session},
+};
+use openai::{
+    AssistantMessage, ChatCompletion, ChatCompletionChunk, ChatCompletionRequest, Choice,
+    ChunkChoice, Delta, ErrorBody, ModelEntry, ModelList,
+};
+use sink::{HttpSink, SinkEvent};
+
+/// Serve the agents with an OpenAI compatible API
+pub async fn serve(bootstrap: Bootstrap, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Serve OpenAI compatible API on {addr}");
+
+    axum::serve(listener, router(Arc::new(bootstrap))).await?;
+
+    Ok(())
+}
+
+/// The models served and the sessions answering them
+pub trait SessionSource: Send + Sync + 'static {
+    type Chat: ChatSession + Send + 'static;
+
+    /// Names of the models
+    fn models(&self) -> Vec<String>;
+
+    /// Model answering a request for `model`, the default one if none is
+    /// given, `None` if it is unknown
+    fn model(&self, model: Option<String>) -> Option<String>;
+
+    /// Session of `model` going on after `history`, `system` is added to
+    /// its preamble
+    fn session(
+        &self,
+        model: &str,
+        system: &str,
+        history: Vec<Message>,
+    ) -> anyhow::Result<Session<Self::Chat>>;
+}
+
+/// Every agent profile is exposed as a model
+impl SessionSource for Bootstrap {
+    type Chat = AgentImpl<CompletionModelHandle<'static>>;
+
+    fn models(&self) -> Vec<String> {
+        self.factory.names()
+    }
+
+    fn model(&self, model: Option<String>) -> Option<String> {
+        match model {
+            Some(name) => self.factory.profile(&name).is_some().then_some(name),
+            None => Some(self.factory.default_profile().name.clone()),
+        }
+    }
+
+    fn session(
+        &self,
+        model: &str,
+        system: &str,
+        history: Vec<Message>,
+    ) -> anyhow::Result<Session<Self::Chat>> {
+        let builder = self.session_builder(Some(model))?;
+        let builder = if system.is_empty() {
+            builder
+        } else {
+            builder.append_preamble(system)
+        };
+
+        Ok(builder
+            .build()
+            .with_history(history)
+            .usage_tracker(self.usage_tracker())
+            .context_manager(self.context_manager()))
+    }
+}
+
+pub fn router<B: SessionSource>(source: Arc<B>) -> Router {
+    Router::new()
+        .route("/v1/models", get(list_models::<B>))
+        .route("/v1/chat/completions", post(chat_completions::<B>))
+        .with_state(source)
+}
+
+async fn list_models<B: SessionSource>(State(source): State<Arc<B>>) -> Json<ModelList> {
+    let data = source
+        .models()
+        .into_iter()
+        .map(|id| ModelEntry {
+            id,
+            object: "model",
+            owned_by: "whisper",
+        })
+        .collect();
+
+    Json(ModelList {
+        object: "list",
+        data,
+    })
+}
+
+async fn chat_completions<B: SessionSource>(
+    State(source): State<Arc<B>>,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Response {
+    let stream = request.stream;
+    let Some(model) = source.model(request.model.clone()) else {
+        let message = format!(
+            "The model `{}` does not exist",
+            request.model.unwrap_or_default()
+        );
+        let body = ErrorBody::new("invalid_request_error", message).code("model_not_found");
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    let conversation = match request.into_conversation() {
+        Ok(conversation) => conversation,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", e),
+    };
+
+    let session = source.session(&model, &conversation.system, conversation.history);
+    let session = match session {
+        Ok(session) => session,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error", e),
+    };
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let task = spawn_ask(session, conversation.prompt, HttpSink::new(sender));
+
+    let completion = Completion::new(model);
+    if stream {
+        Sse::new(completion.stream(receiver))
+            .keep_alive(KeepAlive::default())
+            .into_response()
+    } else {
+        if let Err(e) = task.await {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error", e);
+        }
+        completion.collect(receiver).into_response()
+    }
+}
+
+/// Answer one prompt in the background, failures are reported to the sink
+pub(crate) fn spawn_ask<T, S>(
+    mut session: Session<T>,
+    prompt: String,
+    mut sink: S,
+) -> oneshot::Receiver<(Option<String>, S)>
+where
+    T: ChatSession + Send + 'static,
+    S: ResponseSink + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+
+    spawn_local(Box::new(move || {
+        Box::pin(async move {
+            let answer = match session.ask(&prompt, &mut sink).await {
+                Ok(answer) => Some(answer),
+                Err(e) => {
+                    tracing::error!(error=%e, "Failed to answer remote prompt");
+                    let _ = sink.output_error(&e).await;
+                    None
+                }
+            };
+            let _ = sender.send((answer, sink));
+        })
+    }));
+
+    receiver
+}
+
+/// Build a future on the thread of [`spawn_local`]
+type LocalJob = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// Run a job on a thread of its own, where the jobs run concurrently in a
+/// [`LocalSet`]. The session futures are not `Send`, as rustc could not prove
+/// it for the higher-ranked lifetimes of the completion models.
+fn spawn_local(job: LocalJob) {
+    static JOBS: OnceLock<mpsc::UnboundedSender<LocalJob>> = OnceLock::new();
+
+    let jobs = JOBS.get_or_init(|| {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<LocalJob>();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to build the runtime of the sessions");
+
+        std::thread::Builder::new()
+            .name("sessions".to_string())
+            .spawn(move || {
+                LocalSet::new().block_on(&runtime, async move {
+                    while let Some(job) = receiver.recv().await {
+                        tokio::task::spawn_local(job());
+                    }
+                });
+            })
+            .expect("Failed to spawn the thread of the sessions");
+
+        sender
+    });
+
+    // The thread only stops with the process
+    let _ = jobs.send(job);
+}
+
+fn error_response(status: StatusCode, kind: &'static str, e: impl ToString) -> Response {
+    (status, Json(ErrorBody::new(kind, e.to_string()))).into_response()
+}
+
+/// Metadata shared by all chunks of one completion
+struct Completion {
+    id: String,
+    created: u64,
+    model: String,
+}
+
+impl Completion {
+    fn new(model: String) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self {
+            id: format!("chatcmpl-{}", now.as_nanos()),
+            created: now.as_secs(),
+            model,
+        }
+    }
+
+    fn chunk(&self, delta: Delta, finish_reason: Option<&'static str>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk",
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            usage: None,
+        }
+    }
+
+    /// Turn the sink events into server-sent chunks, ended by `[DONE]`
+    fn stream(
+        self,
+        receiver: UnboundedReceiver<SinkEvent>,
+    ) -> impl Stream<Item = Result<Event, Infallible>> {
+        let first = self.chunk(
+            Delta {
+                role: Some("assistant"),
+                ..Default::default()
+            },
+            None,
+        );
+        let first = Event::default().json_data(first).ok();
+
+        futures::stream::unfold(
+            (self, receiver, first, false),
+            |(completion, mut receiver, pending, done)| async move {
+                if let Some(event) = pending {
+                    return Some((Ok(event), (completion, receiver, None, done)));
+                }
+                if done {
+                    return None;
+                }
+
+                let event = match receiver.recv().await {
+                    Some(SinkEvent::Text(text)) => Event::default().json_data(completion.chunk(
+                        Delta {
+                            content: Some(text),
+                            ..Default::default()
+                        },
+                        None,
+                    )),
+                    Some(SinkEvent::Reasoning(text)) => {
+                        Event::default().json_data(completion.chunk(
+                            Delta {
+                                reasoning_content: Some(text),
+                                ..Default::default()
+                            },
+                            None,
+                        ))
+                    }
+                    Some(SinkEvent::Finished(usage)) => {
+                        let mut chunk = completion.chunk(Delta::default(), Some("stop"));
+                        chunk.usage = usage.map(Into::into);
+                        Event::default().json_data(chunk)
+                    }
+                    Some(SinkEvent::Error(message)) => {
+                        Event::default().json_data(ErrorBody::new("server_error", message))
+                    }
+                    None => {
+                        let done = Event::default().data("[DONE]");
+                        return Some((Ok(done), (completion, receiver, None, true)));
+                    }
+                };
+
+                // Serializing the error body does not fail
+                let event = event
+                    .or_else(|e| {
+                        Event::default().json_data(ErrorBody::new("server_error", e.to_string()))
+                    })
+                    .unwrap_or_default();
+                Some((Ok(event), (completion, receiver, None, false)))
+            },
+        )
+    }
+
+    /// Gather the sink events into a single response
+    fn collect(self, mut receiver: UnboundedReceiver<SinkEvent>) -> Response {
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        let mut usage = None;
+
+        while let Ok(event) = receiver.try_recv() {
+            match event {
+                SinkEvent::Text(text) => content.push_str(&text),
+                SinkEvent::Reasoning(text) => reasoning.push_str(&text),
+                SinkEvent::Finished(u) => usage = u.map(Into::into),
+                SinkEvent::Error(message) => {
+                    return error_response(StatusCode::BAD_GATEWAY, "server_error", message);
+                }
+            }
+        }
+
+        let completion = ChatCompletion {
+            id: self.id,
+            object: "chat.completion",
+            created: self.created,
+            model: self.model,
+            choices: vec![Choice {
+                index: 0,
+                message: AssistantMessage {
+                    role: "assistant",
+                    content,
+                    reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                },
+                finish_reason: "stop",
+            }],
+            usage,
+        };
+
+        Json(completion).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use rig::completion::{Chat, CompletionError, PromptError};
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::agent::session::{ChatImpl, SessionBuilder};
+
+    /// Answer with the size of the history, fail on the prompt `fail`
+    struct Echo;
+
+    impl Chat for Echo {
+        fn chat(
+            &self,
+            prompt: impl Into<Message> + Send,
+            chat_history: Vec<Message>,
+        ) -> impl IntoFuture<Output = Result<String, PromptError>, IntoFuture: Send> {
+            let answer = if prompt.into() == Message::user("fail") {
+                Err(PromptError::CompletionError(
+                    CompletionError::ProviderError("a \"quoted\"\nreason".to_string()),
+                ))
+            } else {
+                Ok(format!("echo after {} messages", chat_history.len()))
+            };
+            async move { answer }
+        }
+    }
+
+    struct Stub;
+
+    impl SessionSource for Stub {
+        type Chat = ChatImpl<Echo>;
+
+        fn models(&self) -> Vec<String> {
+            vec!["echo".to_string()]
+        }
+
+        fn model(&self, model: Option<String>) -> Option<String> {
+            match model.as_deref() {
+                None | Some("echo") => Some("echo".to_string()),
+                Some(_) => None,
+            }
+        }
+
+        fn session(
+            &self,
+            _model: &str,
+            _system: &str,
+            history: Vec<Message>,
+        ) -> anyhow::Result<Session<Self::Chat>> {
+            Ok(SessionBuilder::new()
+                .chat(Echo)
+                .build()
+                .with_history(history))
+        }
+    }
+
+    /// Serve the stub on a free port, return its base URL
+    async fn serve_stub() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(Arc::new(Stub))).await });
+
+        format!("http://{addr}")
+    }
+
+    async fn post(url: &str, body: Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{url}/v1/chat/completions"))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    fn messages() -> Value {
+        json!([
+            { "role": "system", "content": "Be brief" },
+            { "role": "user", "content": "hello" },
+            { "role": "assistant", "content": "hi" },
+            { "role": "user", "content": "how are you?" },
+        ])
+    }
+
+    #[tokio::test]
+    async fn lists_the_models() {
+        let url = serve_stub().await;
+
+        let body = reqwest::get(format!("{url}/v1/models"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let models: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(models["data"][0]["id"], "echo");
+    }
+
+    #[tokio::test]
+    async fn answers_in_one_response() {
+        let url = serve_stub().await;
+
+        let response = post(&url, json!({ "messages": messages() })).await;
+        assert_eq!(response.status(), 200);
+        let completion: Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+
+        assert_eq!(completion["object"], "chat.completion");
+        assert_eq!(completion["model"], "echo");
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["message"]["content"], "echo after 2 messages");
+        assert_eq!(choice["finish_reason"], "stop");
+    }
+
+    #[tokio::test]
+    async fn streams_server_sent_events() {
+        let url = serve_stub().await;
+
+        let response = post(&url, json!({ "messages": messages(), "stream": true })).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let chunks = events(response).await;
+
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        let content: String = chunks
+            .iter()
+            .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+            .collect();
+        assert_eq!(content, "echo after 2 messages");
+        let last = chunks.last().unwrap();
+        assert_eq!(last["object"], "chat.completion.chunk");
+        assert_eq!(last["choices"][0]["finish_reason"], "stop");
+    }
+
+    /// Data of the server-sent events, without the final `[DONE]`
+    async fn events(response: reqwest::Response) -> Vec<Value> {
+        let text = response.text().await.unwrap();
+        let data: Vec<&str> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.last(), Some(&"[DONE]"));
+
+        data[..data.len() - 1]
+            .iter()
+            .map(|event| serde_json::from_str(event).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streams_errors_as_json() {
+        let url = serve_stub().await;
+        let messages = json!([{ "role": "user", "content": "fail" }]);
+
+        let response = post(&url, json!({ "messages": messages, "stream": true })).await;
+        let events = events(response).await;
+
+        let message = events
+            .iter()
+            .find_map(|event| event["error"]["message"].as_str())
+            .unwrap();
+        assert!(message.contains("a \"quoted\"\nreason"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_model() {
+        let url = serve_stub().await;
+
+        let response = post(&url, json!({ "model": "gpt-4o", "messages": messages() })).await;
+        assert_eq!(response.status(), 404);
+        let body: Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+
+        assert_eq!(body["error"]["code"], "model_not_found");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(
+            body["error"]["message"],
+            "The model `gpt-4o` does not exist"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_conversation_without_prompt() {
+        let url = serve_stub().await;
+
+        let response = post(&url, json!({ "messages": [] })).await;
+
+        assert_eq!(response.status(), 400);
+    }
+}
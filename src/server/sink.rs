@@ -0,0 +1,105 @@
+use rig::completion::Usage;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::agent::session::{ResponseSink, SinkError};
+
+/// What the agent produced, forwarded to the HTTP response
+#[derive(Debug)]
+pub enum SinkEvent {
+    Text(String),
+    Reasoning(String),
+    Finished(Option<Usage>),
+    Error(String),
+}
+
+/// Sink forwarding the answer to the handler of a request.
+///
+/// Tool calls are run by whisper, so they are only logged.
+pub struct HttpSink {
+    sender: UnboundedSender<SinkEvent>,
+    is_reasoning: bool,
+}
+
+impl HttpSink {
+    pub fn new(sender: UnboundedSender<SinkEvent>) -> Self {
+        Self {
+            sender,
+            is_reasoning: false,
+        }
+    }
+
+    fn send(&self, event: SinkEvent) -> Result<(), SinkError> {
+        self.sender
+            .send(event)
+            .map_err(|_| SinkError::Output("HTTP client disconnected".to_string()))
+    }
+}
+
+impl ResponseSink for HttpSink {
+    async fn chat_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn user_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_start(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_text(
+        &mut self,
+        content: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        let text = content.to_string();
+
+        if self.is_reasoning {
+            self.send(SinkEvent::Reasoning(text))
+        } else {
+            self.send(SinkEvent::Text(text))
+        }
+    }
+
+    async fn output_tool_call(
+        &mut self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), SinkError> {
+        tracing::info!(%name, %arguments, "Call tool for HTTP client");
+
+        Ok(())
+    }
+
+    async fn output_reason_start(&mut self) -> Result<(), SinkError> {
+        self.is_reasoning = true;
+
+        Ok(())
+    }
+
+    async fn output_reason_end(&mut self) -> Result<(), SinkError> {
+        self.is_reasoning = false;
+
+        Ok(())
+    }
+
+    async fn output_finished(&mut self, usage: &Option<Usage>) -> Result<(), SinkError> {
+        self.send(SinkEvent::Finished(*usage))
+    }
+
+    async fn chat_finished(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn output_error(
+        &mut self,
+        e: &(dyn std::fmt::Display + Send + Sync),
+    ) -> Result<(), SinkError> {
+        self.send(SinkEvent::Error(e.to_string()))
+    }
+
+    /// Stop generating once the client is gone
+    async fn interrupted(&mut self) {
+        self.sender.closed().await
+    }
+}